@@ -1,164 +1,705 @@
-use futures_util::{select, FutureExt};
-use log::{error, info};
+use std::time::{Duration, Instant};
+
+use chat_protocol::{Auth, ChatMessage, ClientFrame, RejectReason, ServerFrame};
+use log::{debug, error, info};
+use tokio::task::{JoinError, JoinHandle};
 
 use crate::{
+    command::{self, Command, Input, COMMANDS},
+    config::Config,
+    history::History,
     input::EventHandler,
-    model::{ChatMessage, Model},
+    login::Submission,
+    model::{ConnectionState, Model},
+    outbox::Outbox,
+    reconnect::Backoff,
     tui::TUI,
-    websocket::Websocket,
-    Event,
+    websocket::{Heartbeat, TlsOptions, Websocket},
+    Edit, Event, Scroll,
 };
 
+/// How long to wait before sending again when the server says there are too
+/// many messages
+static RATE_LIMIT_DELAY: Duration = Duration::from_secs(1);
+
+/// How long connecting and logging in may take
+static CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Who to log in as
+#[derive(Clone)]
+pub struct Login {
+    pub username: String,
+    pub auth: Auth,
+}
+
+/// A connection the server accepted the login on
+pub struct Connected {
+    pub ws: Websocket,
+    pub server: String,
+    /// The login used, with the token the server issued if there is one
+    pub login: Login,
+    pub groups: Vec<String>,
+}
+
+/// Why connecting failed
+pub struct ConnectError {
+    pub message: String,
+    /// Whether the server refused the login, as opposed to not being reachable
+    pub rejected: bool,
+    /// Whether the server may accept the same login later, like when it still
+    /// holds the name for a connection it did not notice was lost
+    pub transient: bool,
+}
+
+impl ConnectError {
+    fn new(message: String) -> Self {
+        Self {
+            message,
+            rejected: false,
+            transient: false,
+        }
+    }
+}
+
 pub struct Application {
     pub url: String,
     pub user_name: String,
-    pub group: String,
+    /// Name the client logged in with, a token only logs into the account
+    /// with this name
+    login_name: String,
+    /// Credentials for the hello, a token once the server issued one
+    pub auth: Auth,
     pub input: EventHandler,
     pub tui: TUI,
     pub model: Model,
     pub ws: Websocket,
+    /// How to check the certificate of a wss:// server
+    pub tls: TlsOptions,
+    /// How often to ping the server and how long to wait for an answer
+    pub heartbeat: Heartbeat,
+    /// Delay between attempts to reconnect
+    backoff: Backoff,
+    /// Attempt to reconnect that is running
+    attempt: Option<JoinHandle<Result<Connected, ConnectError>>>,
+    /// When to send the messages the server asked to wait with
+    flush_at: Option<Instant>,
+    /// Set by `/quit` to leave the UI after the current event
+    quit: bool,
 }
 
 impl Application {
-    pub fn new(ws_url: &str, user_name: &str, group: &str) -> Self {
+    pub fn new(connected: Connected, config: &Config) -> Self {
+        let user_name = connected.login.username;
         Self {
-            url: ws_url.to_string(),
-            user_name: user_name.to_string(),
-            group: group.to_string(),
+            url: connected.server.clone(),
+            auth: connected.login.auth,
             tui: TUI::new(),
             input: EventHandler::new(),
             model: Model {
-                url: ws_url.to_string(),
-                username: user_name.to_string(),
-                group: group.to_string(),
+                url: connected.server,
+                username: user_name.clone(),
+                time_format: config.time_format.clone(),
+                date_format: config.date_format.clone(),
+                history: History::load(config.history_file.clone()),
+                outbox: Outbox::load(config.outbox_file.clone()),
                 ..Default::default()
             },
-            ws: Websocket::loopback(),
+            ws: connected.ws,
+            tls: config.tls.clone(),
+            heartbeat: config.heartbeat,
+            backoff: Backoff::default(),
+            attempt: None,
+            flush_at: None,
+            quit: false,
+            login_name: user_name.clone(),
+            user_name,
         }
     }
 
-    pub async fn run(mut self) -> bool {
-        info!("Starting Application");
+    /// Connect to a server and log in
+    pub async fn connect(
+        submission: Submission,
+        tls: &TlsOptions,
+        heartbeat: Heartbeat,
+    ) -> Result<Connected, ConnectError> {
+        let url = submission.server.clone();
+        let handshake = Self::handshake(submission, tls, heartbeat);
+        match tokio::time::timeout(CONNECT_TIMEOUT, handshake).await {
+            Ok(result) => result,
+            Err(_) => Err(ConnectError::new(format!(
+                "Connecting to {} timed out",
+                url
+            ))),
+        }
+    }
 
-        // Wait for server start
-        self.ws = Self::wait_for_websocket(&self.url).await;
+    /// Introduce ourselves and wait for the server to accept us
+    async fn handshake(
+        submission: Submission,
+        tls: &TlsOptions,
+        heartbeat: Heartbeat,
+    ) -> Result<Connected, ConnectError> {
+        let Submission {
+            server,
+            mut login,
+            groups,
+        } = submission;
 
-        TUI::initialize_panic_handler();
-        self.tui.enter().unwrap();
+        let mut ws = Websocket::connect(&server, tls, heartbeat)
+            .await
+            .map_err(|e| {
+                error!("Failed to connect to server: {:#}", e);
+                ConnectError::new(format!("Could not connect to {}: {}", server, e))
+            })?;
 
-        // Send group to server
-        if let Err(e) = self.ws.send(self.group.clone()) {
-            error!("Faled to inform server which group you are joining: {}", e);
-            return false;
+        let hello = ClientFrame::Hello {
+            username: login.username.clone(),
+            groups: groups.clone(),
+            auth: login.auth.clone(),
+        };
+        if let Err(e) = ws.send(hello) {
+            return Err(ConnectError::new(format!("Failed to log in: {}", e)));
+        }
+        match ws.recieve().await {
+            Ok(ServerFrame::Accepted { token }) => {
+                info!("Logged in as '{}'", login.username);
+                // Reconnect with the token instead of the password
+                if let Some(token) = token {
+                    login.auth = Auth::Token { token };
+                }
+                Ok(Connected {
+                    ws,
+                    server,
+                    login,
+                    groups,
+                })
+            }
+            Ok(ServerFrame::Rejected { reason }) => Err(ConnectError {
+                message: format!("The server rejected the login: {}", reason),
+                rejected: true,
+                transient: matches!(reason, RejectReason::NickTaken { .. }),
+            }),
+            Ok(frame) => Err(ConnectError::new(format!(
+                "Unexpected answer from the server: {:?}",
+                frame
+            ))),
+            Err(e) => {
+                error!("Connection lost during handshake: {}", e);
+                Err(ConnectError::new(
+                    "The connection was lost while logging in".to_string(),
+                ))
+            }
         }
+    }
 
-        let render_thread = tokio::spawn(async move {
-            loop {
-                self.tui.render(&self.model).await.ok().or_else(|| {
-                    info!("Failed to render");
-                    None
-                });
+    /// Credentials to log in again after losing the connection
+    fn login(&self) -> Login {
+        let username = match self.auth {
+            Auth::Token { .. } => &self.login_name,
+            _ => &self.user_name,
+        };
+        Login {
+            username: username.clone(),
+            auth: self.auth.clone(),
+        }
+    }
 
-                let event = select! {
-                    msg = self.input.next().fuse() => {
-                        msg
-                    }
-                    msg = self.ws.recieve().fuse() => {
-                        // Cant recive message, restart
-                        if msg.is_err() {
-                            error!("Failed to recive message, restarting");
-                            Event::Restart
-                        // Deserialize message
-                        } else if let Some(msg) = ChatMessage::deserialize(&msg.unwrap()) {
-                            Event::ReciveMessage(msg)
-                        } else {
-                            continue;
-                        }
-                    }
-                };
+    /// Show the chat, reconnecting whenever the connection is lost. Returns
+    /// how to log in again and why if the server refused the login, None if
+    /// the client should exit.
+    pub async fn run(mut self) -> Option<(Login, String)> {
+        info!("Starting Application");
+        self.tui.terminal.clear().ok()?;
 
-                match event {
-                    Event::Quit | Event::Restart => {
-                        TUI::exit().unwrap();
+        // Redraw the countdown while waiting to reconnect
+        let mut tick = tokio::time::interval(Duration::from_secs(1));
 
-                        return event;
-                    }
-                    other => {
-                        self.update(other);
+        loop {
+            self.model.latency = *self.ws.latency.borrow();
+            self.tui.render(&self.model).await.ok().or_else(|| {
+                info!("Failed to render");
+                None
+            });
+
+            let connected = self.model.connection == ConnectionState::Connected;
+            let retry_at = match self.model.connection {
+                ConnectionState::Reconnecting { at } => Some(at),
+                _ => None,
+            };
+            let event = tokio::select! {
+                event = self.input.next() => event,
+                frame = self.ws.read.recv(), if connected => match frame {
+                    Some(frame) => Event::Recieve(frame),
+                    None => Event::Restart,
+                },
+                Ok(()) = self.ws.latency.changed(), if connected => Event::Refresh,
+                _ = sleep_until(retry_at) => Event::Reconnect,
+                _ = sleep_until(self.flush_at), if connected => {
+                    self.flush_at = None;
+                    Event::Flush
+                }
+                _ = tick.tick(), if retry_at.is_some() => Event::Refresh,
+                result = finished(&mut self.attempt) => {
+                    self.attempt = None;
+                    if let Some(reason) = self.on_reconnect(result) {
+                        return Some((self.login(), reason));
                     }
+                    Event::Refresh
                 }
-            }
-        });
+            };
 
-        tokio::select! {
-            e = render_thread => {
-                match e {
-                    Ok(Event::Quit) => {
-                        TUI::exit().unwrap();
-                        return false;
-                    }
-                    Ok(Event::Restart) => {
-                        TUI::exit().unwrap();
-                        info!("Connection lost");
-                        return true;
+            match event {
+                Event::Quit => return None,
+                Event::Cancel if self.model.search.is_none() && self.model.notices.is_empty() => {
+                    return None;
+                }
+                other => {
+                    self.update(other);
+                    if self.quit {
+                        return None;
                     }
-                    _ => {info!("Render thread exited")}
                 }
-            },
+            }
+        }
+    }
+
+    /// Wait before the next attempt to reconnect, or give up after too many
+    fn schedule_reconnect(&mut self) {
+        match self.backoff.next_delay() {
+            Some(delay) => {
+                info!("Reconnecting in {:?}", delay);
+                self.model.connection = ConnectionState::Reconnecting {
+                    at: Instant::now() + delay,
+                };
+            }
+            None => {
+                self.model.connection = ConnectionState::Offline;
+                self.model
+                    .error("Could not reconnect to the server, /reconnect to try again");
+            }
         }
+    }
 
-        info!("Exiting UI");
-        false
+    /// Start an attempt to reconnect in the background
+    fn reconnect(&mut self) {
+        if self.attempt.is_some() {
+            return;
+        }
+        self.model.connection = ConnectionState::Connecting;
+        // The groups are joined after connecting, to resume where they left off
+        let submission = Submission {
+            server: self.url.clone(),
+            login: self.login(),
+            groups: Vec::new(),
+        };
+        let tls = self.tls.clone();
+        let heartbeat = self.heartbeat;
+        self.attempt = Some(tokio::spawn(async move {
+            Application::connect(submission, &tls, heartbeat).await
+        }));
+    }
+
+    /// Use the new connection, or try again later. Returns why if the server
+    /// refused the login for good, reconnecting does not help then.
+    fn on_reconnect(
+        &mut self,
+        result: Result<Result<Connected, ConnectError>, JoinError>,
+    ) -> Option<String> {
+        let result = result.unwrap_or_else(|e| Err(ConnectError::new(e.to_string())));
+        match result {
+            Ok(connected) => {
+                info!("Reconnected to {}", self.url);
+                self.ws = connected.ws;
+                self.auth = connected.login.auth;
+                let nick = std::mem::replace(&mut self.user_name, connected.login.username);
+                self.model.username = self.user_name.clone();
+                self.backoff.reset();
+                self.model.connection = ConnectionState::Connected;
+                // Join the shown groups again and get the messages missed since
+                // the newest one there is
+                let groups: Vec<(String, Option<i64>)> = self
+                    .model
+                    .groups
+                    .iter()
+                    .map(|g| (g.name.clone(), g.messages.iter().filter_map(|m| m.id).max()))
+                    .collect();
+                for (group, since) in groups {
+                    self.send(ClientFrame::Join { group, since });
+                }
+                // Logging in with a token uses the name of the account, take
+                // the name used before back
+                if nick != self.user_name {
+                    self.send(ClientFrame::Nick { nick });
+                }
+                // Requests for older messages were lost with the connection
+                for group in &mut self.model.groups {
+                    group.loading_older = false;
+                }
+                None
+            }
+            Err(e) if e.rejected && !e.transient => Some(e.message),
+            Err(e) => {
+                error!("Failed to reconnect: {}", e.message);
+                self.schedule_reconnect();
+                None
+            }
+        }
     }
 
     pub fn update(&mut self, event: Event) {
         match event {
-            Event::Input(c) => {
-                self.model.text_area.push(c);
-            }
+            Event::Edit(edit) => self.edit(edit),
             Event::Refresh => {}
+            Event::Cancel => {
+                if self.model.search.take().is_none() {
+                    self.model.notices.clear();
+                }
+            }
+            Event::SearchHistory => self.model.search_older(),
             Event::Quit => {
                 unreachable!("Quit event should be handled in run()");
             }
             Event::Restart => {
-                unreachable!("Restart event should be handled in run()");
+                error!("Connection to the server lost");
+                self.ws = Websocket::disconnected();
+                // Unanswered messages are sent again after reconnecting
+                self.model.outbox.disconnected();
+                self.schedule_reconnect();
+            }
+            Event::Reconnect => self.reconnect(),
+            Event::Flush => {
+                let groups: Vec<String> =
+                    self.model.groups.iter().map(|g| g.name.clone()).collect();
+                for group in groups {
+                    self.flush(&group);
+                }
             }
             Event::Resize { width, height } => {
                 self.tui.resize(width, height);
             }
-            Event::ReciveMessage(msg) => {
-                self.model.messages.push(msg);
-            }
+            Event::Recieve(frame) => self.on_frame(frame),
+            Event::Send if self.model.search.is_some() => self.model.accept_search(),
             Event::Send => {
-                if self.model.text_area.is_empty() {
+                if self.model.text_area.is_blank() {
                     return;
                 }
-                let msg = ChatMessage {
-                    username: self.user_name.clone(),
-                    message: self.model.text_area.clone(),
+                self.model.notices.clear();
+                // Keep the text in the input box to fix a mistyped command
+                let input = match command::parse(self.model.text_area.text()) {
+                    Ok(input) => input,
+                    Err(problem) => return self.model.error(problem),
                 };
+                if input.needs_server() && self.model.connection != ConnectionState::Connected {
+                    return self.model.error("Not connected to the server");
+                }
+
+                let text = self.model.text_area.take();
+                self.model.recall = None;
+                if let Some(group) = self.model.active_group() {
+                    let group = group.name.clone();
+                    self.model.history.push(&group, text);
+                }
 
-                self.model.text_area.clear();
-                self.ws.send(msg.serialize()).unwrap();
+                match input {
+                    Input::Message(message) => self.send_message(message, false),
+                    Input::Command(command) => self.command(command),
+                }
             }
-            Event::Backspace => {
-                self.model.text_area.pop();
+            Event::Scroll(scroll) => self.scroll(scroll),
+            Event::NextGroup => self.model.next_group(),
+            Event::PreviousGroup => self.model.previous_group(),
+            Event::SelectGroup(index) => self.model.select(index),
+        };
+    }
+
+    /// Send a message to the shown group. It stays in the outbox until the
+    /// server confirms it and is sent again after reconnecting.
+    fn send_message(&mut self, message: String, action: bool) {
+        let Some(group) = self.model.active_group() else {
+            self.model.error("Join a group first, with /join <group>");
+            return;
+        };
+        let group = group.name.clone();
+        self.model.outbox.push(&self.url, &group, message, action);
+
+        if self.model.connection == ConnectionState::Connected {
+            self.flush(&group);
+        }
+    }
+
+    /// Send the next queued message of a group, the ones after it follow when
+    /// the server answered
+    fn flush(&mut self, group: &str) {
+        if let Some(frame) = self.model.outbox.next(&self.url, group) {
+            self.send(frame);
+        }
+    }
+
+    /// Send a frame, telling the user if the connection is gone
+    fn send(&mut self, frame: ClientFrame) {
+        if let Err(e) = self.ws.send(frame) {
+            self.model.error(e.to_string());
+        }
+    }
+
+    fn command(&mut self, command: Command) {
+        match command {
+            Command::Join(group) => self.send(ClientFrame::Join { group, since: None }),
+            Command::Part(group) => {
+                let Some(group) = group.or_else(|| Some(self.model.active_group()?.name.clone()))
+                else {
+                    self.model.error("Not a member of any group");
+                    return;
+                };
+                self.send(ClientFrame::Part { group });
+            }
+            Command::Nick(nick) => self.send(ClientFrame::Nick { nick }),
+            Command::Me(action) => self.send_message(action, true),
+            Command::Quit => self.quit = true,
+            Command::Clear => self.model.clear(),
+            Command::Reconnect => match self.model.connection {
+                ConnectionState::Connected => self.model.notice("Already connected"),
+                ConnectionState::Connecting => self.model.notice("Already reconnecting"),
+                ConnectionState::Reconnecting { .. } | ConnectionState::Offline => {
+                    self.backoff.reset();
+                    self.reconnect();
+                }
+            },
+            Command::Retry => {
+                let Some(group) = self.model.active_group().map(|g| g.name.clone()) else {
+                    return;
+                };
+                match self.model.outbox.retry(&self.url, &group) {
+                    0 => self.model.notice("No failed messages to send again"),
+                    _ if self.model.connection == ConnectionState::Connected => self.flush(&group),
+                    _ => {}
+                }
+            }
+            Command::Discard => {
+                let Some(group) = self.model.active_group().map(|g| g.name.clone()) else {
+                    return;
+                };
+                match self.model.outbox.discard(&self.url, &group) {
+                    0 => self.model.notice("No failed messages to drop"),
+                    1 => self.model.notice("Dropped 1 failed message"),
+                    n => self.model.notice(format!("Dropped {} failed messages", n)),
+                }
+            }
+            Command::Help => {
+                for spec in COMMANDS {
+                    self.model
+                        .notice(format!("{:<16} {}", spec.usage_line(), spec.help));
+                }
+                self.model.notice(format!(
+                    "{:<16} {}",
+                    "//<text>", "Send a message starting with /"
+                ));
+            }
+        }
+    }
+
+    /// Edit the input, Up and Down recall sent messages if the input is
+    /// empty or already shows one
+    fn edit(&mut self, edit: Edit) {
+        if self.model.search.is_some() {
+            self.model.search_edit(edit);
+            return;
+        }
+        match edit {
+            Edit::Up if self.model.recall_older() => {}
+            Edit::Down if self.model.recall_newer() => {}
+            Edit::Up | Edit::Down => self.model.text_area.edit(edit),
+            edit => {
+                // Editing a recalled message turns it into a new draft
+                self.model.recall = None;
+                self.model.text_area.edit(edit);
+            }
+        }
+    }
+
+    fn scroll(&mut self, scroll: Scroll) {
+        let top = self.tui.viewport.max_scroll;
+        // Keep one line of the previous page visible
+        let page = (self.tui.viewport.height as usize).saturating_sub(1).max(1);
+
+        let load = match scroll {
+            Scroll::Up(amount) => self.model.scroll_up(amount, top),
+            Scroll::PageUp => self.model.scroll_up(page, top),
+            Scroll::Top => self.model.scroll_up(usize::MAX, top),
+            Scroll::Down(amount) => {
+                self.model.scroll_down(amount);
+                None
+            }
+            Scroll::PageDown => {
+                self.model.scroll_down(page);
+                None
+            }
+            Scroll::Bottom => {
+                self.model.scroll_down(usize::MAX);
+                None
             }
         };
+
+        if let Some((group, before)) = load {
+            info!("Loading messages before {} in '{}'", before, group);
+            self.send(ClientFrame::LoadOlder { group, before });
+        }
     }
 
-    async fn wait_for_websocket(url: &str) -> Websocket {
-        loop {
-            let connection = Websocket::connect(url).await;
-            match connection {
-                Ok(c) => {
-                    return c;
+    fn on_frame(&mut self, frame: ServerFrame) {
+        match frame {
+            ServerFrame::Joined { group } => {
+                info!("Joined group '{}'", group);
+                self.model.join(&group);
+                self.flush(&group);
+            }
+            ServerFrame::JoinFailed { group, reason } => {
+                error!("Failed to join '{}': {}", group, reason);
+                self.model
+                    .error(format!("Could not join '{}': {}", group, reason));
+            }
+            ServerFrame::Parted { group } => {
+                info!("Left group '{}'", group);
+                self.model.part(&group);
+            }
+            ServerFrame::Chat { group, message } => {
+                if let Some(ref key) = message.key {
+                    self.model.outbox.remove(key);
                 }
-                Err(e) => {
-                    error!("Failed to connect to server: {}", e);
-                    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                let lines = self.model.group(&group).map_or(0, |g| {
+                    let position = g.position(&message);
+                    let previous = position.checked_sub(1).map(|i| &g.messages[i]);
+                    let count = |previous: Option<&ChatMessage>, message: &ChatMessage| {
+                        self.tui.line_count(previous, message, &self.model)
+                    };
+                    let lines = count(previous, &message);
+                    // The message after it may look different with a new neighbour
+                    match g.messages.get(position) {
+                        Some(next) => (lines + count(Some(&message), next))
+                            .saturating_sub(count(previous, next)),
+                        None => lines,
+                    }
+                });
+                if !self.model.push_message(&group, message, lines) {
+                    error!("Message for unknown group '{}'", group);
                 }
             }
+            ServerFrame::History {
+                group,
+                messages,
+                has_more,
+                since,
+            } => {
+                // Pending messages the server stored before the connection was lost
+                for key in messages.iter().filter_map(|m| m.key.as_deref()) {
+                    self.model.outbox.remove(key);
+                }
+                match (self.model.group_mut(&group), since) {
+                    // Messages missed while reconnecting, the ones that arrived
+                    // live in the meantime are skipped
+                    (Some(_), Some(_)) => {
+                        for message in messages {
+                            self.on_frame(ServerFrame::Chat {
+                                group: group.clone(),
+                                message,
+                            });
+                        }
+                    }
+                    (Some(existing), None) => {
+                        // Messages sent live after the server read the history
+                        // can arrive before it, they are kept
+                        let newest = messages.iter().filter_map(|m| m.id).max();
+                        let live: Vec<_> = existing
+                            .messages
+                            .drain(..)
+                            .filter(|m| m.id.is_some() && m.id > newest)
+                            .collect();
+                        existing.messages = messages;
+                        existing.messages.extend(live);
+                        existing.has_more = has_more;
+                        existing.scroll = 0;
+                        existing.new_below = 0;
+                    }
+                    (None, _) => error!("History for unknown group '{}'", group),
+                }
+            }
+            ServerFrame::Older {
+                group,
+                messages,
+                has_more,
+            } => match self.model.group_mut(&group) {
+                Some(group) => {
+                    group.messages.splice(0..0, messages);
+                    group.has_more = has_more;
+                    group.loading_older = false;
+                }
+                None => error!("Older messages for unknown group '{}'", group),
+            },
+            ServerFrame::Delivered { group, key, id } => {
+                debug!("Message to '{}' delivered with id {}", group, id);
+                if let Some(key) = key {
+                    self.model.outbox.remove(&key);
+                }
+                self.flush(&group);
+            }
+            ServerFrame::Undelivered {
+                group,
+                key: Some(key),
+                reason: RejectReason::RateLimited,
+            } => {
+                debug!("Sending too fast to '{}', waiting", group);
+                self.model.outbox.requeue(&key);
+                self.flush_at
+                    .get_or_insert_with(|| Instant::now() + RATE_LIMIT_DELAY);
+            }
+            ServerFrame::Undelivered { group, key, reason } => {
+                error!("Message to '{}' not delivered: {}", group, reason);
+                if let Some(key) = key {
+                    self.model.outbox.fail(&key, reason.clone());
+                }
+                self.model.error(format!(
+                    "Could not send the message to '{}': {}, /retry to send it again or /discard to drop it",
+                    group, reason
+                ));
+                self.flush(&group);
+            }
+            ServerFrame::Accepted { .. } | ServerFrame::Rejected { .. } => {
+                error!("Unexpected handshake frame: {:?}", frame);
+            }
+            ServerFrame::NickChanged { old, new } => {
+                if old == self.user_name {
+                    self.model.notice(format!("You are now known as {}", new));
+                    self.user_name = new.clone();
+                    self.model.username = new;
+                } else {
+                    self.model
+                        .notice(format!("{} is now known as {}", old, new));
+                }
+            }
+            ServerFrame::NickFailed { nick, reason } => {
+                self.model
+                    .error(format!("Could not change name to '{}': {}", nick, reason));
+            }
+            ServerFrame::Error { reason } => {
+                error!("Server error: {}", reason);
+                self.model.error(reason);
+            }
         }
     }
 }
+
+/// Wait until the given time, forever if there is none
+async fn sleep_until(at: Option<Instant>) {
+    match at {
+        Some(at) => tokio::time::sleep_until(at.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Wait for a task to finish, forever if there is none
+async fn finished<T>(task: &mut Option<JoinHandle<T>>) -> Result<T, JoinError> {
+    match task {
+        Some(task) => task.await,
+        None => std::future::pending().await,
+    }
+}
@@ -0,0 +1,214 @@
+/// A command typed into the input box
+#[derive(Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Join a group
+    Join(String),
+    /// Leave a group, the shown one if none is given
+    Part(Option<String>),
+    /// Change the name shown to others
+    Nick(String),
+    /// Send a message describing an action
+    Me(String),
+    Quit,
+    /// Remove the loaded messages of the shown group from the screen
+    Clear,
+    /// Connect again right away instead of waiting
+    Reconnect,
+    /// Send the messages the server refused in the shown group again
+    Retry,
+    /// Drop the messages the server refused in the shown group
+    Discard,
+    Help,
+}
+
+/// What the user typed into the input box
+#[derive(Debug, PartialEq, Eq)]
+pub enum Input {
+    Message(String),
+    Command(Command),
+}
+
+/// A command that can be typed after a `/`
+pub struct Spec {
+    pub name: &'static str,
+    /// Arguments of the command, shown in the help
+    pub usage: &'static str,
+    pub help: &'static str,
+    /// Turn the arguments into the command, or explain what is wrong with them
+    parse: fn(&str) -> Result<Command, String>,
+}
+
+/// All known commands, in the order they are listed by `/help`
+pub static COMMANDS: &[Spec] = &[
+    Spec {
+        name: "join",
+        usage: "<group>",
+        help: "Join a group",
+        parse: |args| required(args, "group").map(Command::Join),
+    },
+    Spec {
+        name: "part",
+        usage: "[group]",
+        help: "Leave a group, the shown one if none is given",
+        parse: |args| Ok(Command::Part(optional(args))),
+    },
+    Spec {
+        name: "nick",
+        usage: "<name>",
+        help: "Change your name",
+        parse: |args| required(args, "name").map(Command::Nick),
+    },
+    Spec {
+        name: "me",
+        usage: "<action>",
+        help: "Describe what you are doing",
+        parse: |args| required(args, "action").map(Command::Me),
+    },
+    Spec {
+        name: "clear",
+        usage: "",
+        help: "Clear the messages of the shown group",
+        parse: |args| no_arguments(args, Command::Clear),
+    },
+    Spec {
+        name: "reconnect",
+        usage: "",
+        help: "Reconnect to the server right away",
+        parse: |args| no_arguments(args, Command::Reconnect),
+    },
+    Spec {
+        name: "retry",
+        usage: "",
+        help: "Send the failed messages of the shown group again",
+        parse: |args| no_arguments(args, Command::Retry),
+    },
+    Spec {
+        name: "discard",
+        usage: "",
+        help: "Drop the failed messages of the shown group",
+        parse: |args| no_arguments(args, Command::Discard),
+    },
+    Spec {
+        name: "help",
+        usage: "",
+        help: "Show this list",
+        parse: |args| no_arguments(args, Command::Help),
+    },
+    Spec {
+        name: "quit",
+        usage: "",
+        help: "Close the client",
+        parse: |args| no_arguments(args, Command::Quit),
+    },
+];
+
+/// Parse the text of the input box. Text starting with `/` is a command,
+/// `//` sends a message starting with a single `/`.
+pub fn parse(text: &str) -> Result<Input, String> {
+    let Some(line) = text.strip_prefix('/') else {
+        return Ok(Input::Message(text.to_string()));
+    };
+    if line.starts_with('/') {
+        return Ok(Input::Message(line.to_string()));
+    }
+
+    let (name, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let spec = COMMANDS
+        .iter()
+        .find(|spec| spec.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("Unknown command /{}, type /help for a list", name))?;
+    (spec.parse)(args.trim())
+        .map(Input::Command)
+        .map_err(|problem| format!("/{}: {} (usage: {})", spec.name, problem, spec.usage_line()))
+}
+
+impl Input {
+    /// Whether the input is sent to the server right away, so it needs a
+    /// connection. Messages wait in the outbox instead.
+    pub fn needs_server(&self) -> bool {
+        match self {
+            Input::Message(_) => false,
+            Input::Command(command) => matches!(
+                command,
+                Command::Join(_) | Command::Part(_) | Command::Nick(_)
+            ),
+        }
+    }
+}
+
+impl Spec {
+    /// The command with its arguments, like `/join <group>`
+    pub fn usage_line(&self) -> String {
+        if self.usage.is_empty() {
+            format!("/{}", self.name)
+        } else {
+            format!("/{} {}", self.name, self.usage)
+        }
+    }
+}
+
+fn required(args: &str, what: &str) -> Result<String, String> {
+    if args.is_empty() {
+        Err(format!("missing {}", what))
+    } else {
+        Ok(args.to_string())
+    }
+}
+
+fn optional(args: &str) -> Option<String> {
+    (!args.is_empty()).then(|| args.to_string())
+}
+
+fn no_arguments(args: &str, command: Command) -> Result<Command, String> {
+    if args.is_empty() {
+        Ok(command)
+    } else {
+        Err("takes no arguments".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_without_slash_is_a_message() {
+        assert_eq!(
+            parse("hi /join"),
+            Ok(Input::Message("hi /join".to_string()))
+        );
+    }
+
+    #[test]
+    fn double_slash_sends_a_single_one() {
+        assert_eq!(parse("//join"), Ok(Input::Message("/join".to_string())));
+    }
+
+    #[test]
+    fn commands_take_their_arguments() {
+        assert_eq!(
+            parse("/JOIN  rust "),
+            Ok(Input::Command(Command::Join("rust".to_string())))
+        );
+        assert_eq!(parse("/part"), Ok(Input::Command(Command::Part(None))));
+        assert_eq!(
+            parse("/me waves at everyone"),
+            Ok(Input::Command(Command::Me("waves at everyone".to_string())))
+        );
+    }
+
+    #[test]
+    fn wrong_arguments_are_explained() {
+        assert_eq!(
+            parse("/nick"),
+            Err("/nick: missing name (usage: /nick <name>)".to_string())
+        );
+        assert_eq!(
+            parse("/quit now"),
+            Err("/quit: takes no arguments (usage: /quit)".to_string())
+        );
+        assert!(parse("/nope")
+            .unwrap_err()
+            .starts_with("Unknown command /nope"));
+    }
+}
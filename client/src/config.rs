@@ -0,0 +1,221 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Context;
+use clap::Parser;
+use serde::Deserialize;
+
+use crate::{
+    model::{DEFAULT_DATE_FORMAT, DEFAULT_TIME_FORMAT},
+    websocket::{Heartbeat, TlsOptions},
+};
+
+/// Seconds between pings to the server, unless the config file says otherwise
+static DEFAULT_HEARTBEAT_INTERVAL: u64 = 15;
+/// Seconds without hearing from the server before reconnecting
+static DEFAULT_HEARTBEAT_TIMEOUT: u64 = 45;
+
+/// Command line arguments, they override the profile and the config file
+#[derive(Parser, Debug)]
+#[command(version, about = "Terminal chat client")]
+pub struct Args {
+    /// Profile from the config file to connect with
+    #[arg(short, long, env = "CHAT_PROFILE")]
+    pub profile: Option<String>,
+    /// Config file, `chat-client/config.toml` in the config directory by default
+    #[arg(short, long, env = "CHAT_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Server to connect to, like ws://127.0.0.1:9001
+    #[arg(short, long)]
+    pub server: Option<String>,
+    /// Name to chat with
+    #[arg(short, long)]
+    pub name: Option<String>,
+    /// Groups to join, separated by ',' or given several times
+    #[arg(short, long = "group", value_delimiter = ',')]
+    pub groups: Vec<String>,
+    /// Log into the account of the name with a password
+    #[arg(long, conflicts_with_all = ["register", "guest"])]
+    pub login: bool,
+    /// Register the name as a new account
+    #[arg(long, conflicts_with = "guest")]
+    pub register: bool,
+    /// Chat as a guest, even if the profile logs into an account
+    #[arg(long)]
+    pub guest: bool,
+    /// PEM certificate of an additional CA to trust for wss://
+    #[arg(long, env = "CHAT_TLS_CA")]
+    pub tls_ca: Option<PathBuf>,
+    /// SHA-256 fingerprint of the server certificate, for self-signed certificates
+    #[arg(long, env = "CHAT_TLS_PIN")]
+    pub tls_pin: Option<String>,
+    /// strftime format of the time in front of messages
+    #[arg(long, env = "CHAT_TIME_FORMAT")]
+    pub time_format: Option<String>,
+    /// strftime format of the line shown when the day changes
+    #[arg(long, env = "CHAT_DATE_FORMAT")]
+    pub date_format: Option<String>,
+    /// File the sent messages are saved to
+    #[arg(long, env = "CHAT_HISTORY_FILE")]
+    pub history_file: Option<PathBuf>,
+}
+
+/// Content of the config file
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    time_format: Option<String>,
+    date_format: Option<String>,
+    history_file: Option<PathBuf>,
+    recent_file: Option<PathBuf>,
+    outbox_file: Option<PathBuf>,
+    /// Seconds between pings to the server
+    heartbeat_interval: Option<u64>,
+    /// Seconds without hearing from the server before reconnecting
+    heartbeat_timeout: Option<u64>,
+    profiles: HashMap<String, Profile>,
+}
+
+/// A server to connect to and how, from the config file
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub server: Option<String>,
+    pub name: Option<String>,
+    pub groups: Vec<String>,
+    /// Log into the account of the name with a password instead of as a guest
+    pub account: bool,
+    pub tls_ca: Option<PathBuf>,
+    pub tls_pin: Option<String>,
+}
+
+/// How to log in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AuthMethod {
+    #[default]
+    Guest,
+    Login,
+    Register,
+}
+
+/// Settings of the client, from the arguments, the chosen profile and the
+/// config file. Whatever is None is filled in on the login screen.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub server: Option<String>,
+    pub name: Option<String>,
+    pub groups: Option<Vec<String>>,
+    pub auth: Option<AuthMethod>,
+    pub tls: TlsOptions,
+    pub time_format: String,
+    pub date_format: String,
+    /// File the sent messages are saved to, nothing is saved if there is none
+    pub history_file: Option<PathBuf>,
+    /// File the servers offered on the login screen are saved to
+    pub recent_file: Option<PathBuf>,
+    /// File the messages that were not sent yet are saved to
+    pub outbox_file: Option<PathBuf>,
+    pub heartbeat: Heartbeat,
+}
+
+impl Config {
+    /// Read the config file and apply the chosen profile and the arguments on top
+    pub fn load(args: Args) -> anyhow::Result<Self> {
+        let file = match args.config {
+            Some(ref path) => Some(read_config(path)?),
+            // The default config file does not have to exist
+            None => match default_path() {
+                Some(path) if path.exists() => Some(read_config(&path)?),
+                _ => None,
+            },
+        }
+        .unwrap_or_default();
+
+        let profile = match args.profile {
+            Some(ref name) => file.profiles.get(name).cloned().with_context(|| {
+                let mut known: Vec<&String> = file.profiles.keys().collect();
+                known.sort();
+                format!("Unknown profile '{}', known profiles: {:?}", name, known)
+            })?,
+            None => Profile::default(),
+        };
+
+        let groups = match (args.groups, profile.groups) {
+            (groups, _) if !groups.is_empty() => Some(groups),
+            (_, groups) if !groups.is_empty() => Some(groups),
+            _ => None,
+        };
+        let auth = if args.login {
+            Some(AuthMethod::Login)
+        } else if args.register {
+            Some(AuthMethod::Register)
+        } else if args.guest {
+            Some(AuthMethod::Guest)
+        } else if profile.account {
+            Some(AuthMethod::Login)
+        } else {
+            None
+        };
+
+        let heartbeat = Heartbeat {
+            interval: Duration::from_secs(
+                file.heartbeat_interval
+                    .unwrap_or(DEFAULT_HEARTBEAT_INTERVAL),
+            ),
+            timeout: Duration::from_secs(
+                file.heartbeat_timeout.unwrap_or(DEFAULT_HEARTBEAT_TIMEOUT),
+            ),
+        };
+        if heartbeat.interval.is_zero() {
+            anyhow::bail!("The heartbeat interval must be at least 1 second");
+        }
+        if heartbeat.timeout <= heartbeat.interval {
+            anyhow::bail!("The heartbeat timeout must be longer than the interval");
+        }
+
+        Ok(Self {
+            server: args.server.or(profile.server),
+            name: args.name.or(profile.name),
+            groups,
+            auth,
+            tls: TlsOptions {
+                ca: args.tls_ca.or(profile.tls_ca),
+                pin: args.tls_pin.or(profile.tls_pin),
+            },
+            time_format: args
+                .time_format
+                .or(file.time_format)
+                .unwrap_or_else(|| DEFAULT_TIME_FORMAT.to_string()),
+            date_format: args
+                .date_format
+                .or(file.date_format)
+                .unwrap_or_else(|| DEFAULT_DATE_FORMAT.to_string()),
+            history_file: args
+                .history_file
+                .or(file.history_file)
+                .or_else(|| data_file("history.json")),
+            recent_file: file.recent_file.or_else(|| data_file("recent.json")),
+            outbox_file: file.outbox_file.or_else(|| data_file("outbox.json")),
+            heartbeat,
+        })
+    }
+}
+
+/// `chat-client/config.toml` in the config directory of the platform
+pub fn default_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|d| d.join("chat-client").join("config.toml"))
+}
+
+/// A file in `chat-client` in the data directory of the platform
+fn data_file(name: &str) -> Option<PathBuf> {
+    dirs::data_dir().map(|d| d.join("chat-client").join(name))
+}
+
+fn read_config(path: &Path) -> anyhow::Result<ConfigFile> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    toml::from_str(&content).with_context(|| format!("Invalid config file {}", path.display()))
+}
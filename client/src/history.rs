@@ -0,0 +1,43 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use crate::json_file::JsonFile;
+
+/// How many sent messages are remembered per group
+static MAX_ENTRIES: usize = 500;
+
+/// Messages the user sent, per group and oldest first, kept in a local file
+/// so they survive restarts of the client
+#[derive(Default)]
+pub struct History {
+    file: JsonFile,
+    groups: HashMap<String, Vec<String>>,
+}
+
+impl History {
+    /// Load the history from a file, it is saved there as well
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let file = JsonFile::new(path, "message history");
+        Self {
+            groups: file.load(),
+            file,
+        }
+    }
+
+    /// Sent messages of a group, oldest first
+    pub fn entries(&self, group: &str) -> &[String] {
+        self.groups.get(group).map_or(&[], Vec::as_slice)
+    }
+
+    /// Remember a sent message and save the history
+    pub fn push(&mut self, group: &str, message: String) {
+        let entries = self.groups.entry(group.to_string()).or_default();
+        // Sending the same message again does not need another entry
+        if entries.last() != Some(&message) {
+            entries.push(message);
+        }
+        if entries.len() > MAX_ENTRIES {
+            entries.drain(..entries.len() - MAX_ENTRIES);
+        }
+        self.file.save(&self.groups);
+    }
+}
@@ -1,13 +1,18 @@
-use crossterm::event::{EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::event::{
+    EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseEvent, MouseEventKind,
+};
 use futures_util::{FutureExt, StreamExt};
 use log::{debug, error};
 use tokio::{sync::mpsc, task::JoinHandle};
 
-use crate::Event;
+use crate::{Edit, Event, Scroll};
+
+/// How many lines one step of the mouse wheel scrolls
+static MOUSE_SCROLL_AMOUNT: usize = 3;
 
 pub struct EventHandler {
     channel: mpsc::UnboundedReceiver<Event>,
-    _task: JoinHandle<()>,
+    task: JoinHandle<()>,
 }
 
 impl EventHandler {
@@ -34,7 +39,7 @@ impl EventHandler {
 
         Self {
             channel: events_rx,
-            _task: task,
+            task,
         }
     }
 
@@ -49,15 +54,26 @@ impl EventHandler {
                 ..
             }) => Some(Event::Quit),
             CEvent::Resize(width, height) => Some(Event::Resize { width, height }),
-            CEvent::Key(KeyEvent {
-                code,
-                kind: KeyEventKind::Press,
-                ..
-            }) => Self::handle_key(code),
+            CEvent::Key(
+                key @ KeyEvent {
+                    kind: KeyEventKind::Press,
+                    ..
+                },
+            ) => Self::handle_key(key),
             CEvent::Key(KeyEvent {
                 kind: KeyEventKind::Release,
                 ..
             }) => None,
+            CEvent::Mouse(MouseEvent {
+                kind: MouseEventKind::ScrollUp,
+                ..
+            }) => Some(Event::Scroll(Scroll::Up(MOUSE_SCROLL_AMOUNT))),
+            CEvent::Mouse(MouseEvent {
+                kind: MouseEventKind::ScrollDown,
+                ..
+            }) => Some(Event::Scroll(Scroll::Down(MOUSE_SCROLL_AMOUNT))),
+            CEvent::Mouse(_) => None,
+            CEvent::Paste(text) => Some(Event::Edit(Edit::Paste(text))),
             e => {
                 debug!("Unhandled event: {:?}", e);
                 None
@@ -66,12 +82,64 @@ impl EventHandler {
     }
 
     /// Handle a key event from the terminal
-    fn handle_key(key: KeyCode) -> Option<Event> {
-        match key {
-            KeyCode::Esc => Some(Event::Quit),
+    fn handle_key(key: KeyEvent) -> Option<Event> {
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        let alt = key.modifiers.contains(KeyModifiers::ALT);
+
+        match key.code {
+            KeyCode::Esc => Some(Event::Cancel),
+            // Many terminals only report Alt+Enter, not Shift+Enter
+            KeyCode::Enter if alt || key.modifiers.contains(KeyModifiers::SHIFT) => {
+                Some(Event::Edit(Edit::Insert('\n')))
+            }
             KeyCode::Enter => Some(Event::Send),
-            KeyCode::Backspace => Some(Event::Backspace),
-            KeyCode::Char(c) => Some(Event::Input(c)),
+            KeyCode::Backspace if alt => Some(Event::Edit(Edit::DeleteWordLeft)),
+            KeyCode::Backspace => Some(Event::Edit(Edit::Backspace)),
+            KeyCode::Delete => Some(Event::Edit(Edit::Delete)),
+            KeyCode::Left if control => Some(Event::Edit(Edit::WordLeft)),
+            KeyCode::Right if control => Some(Event::Edit(Edit::WordRight)),
+            KeyCode::Left => Some(Event::Edit(Edit::Left)),
+            KeyCode::Right => Some(Event::Edit(Edit::Right)),
+            KeyCode::Up => Some(Event::Edit(Edit::Up)),
+            KeyCode::Down => Some(Event::Edit(Edit::Down)),
+            KeyCode::Home if control => Some(Event::Scroll(Scroll::Top)),
+            KeyCode::End if control => Some(Event::Scroll(Scroll::Bottom)),
+            KeyCode::Home => Some(Event::Edit(Edit::Home)),
+            KeyCode::End => Some(Event::Edit(Edit::End)),
+            KeyCode::PageUp => Some(Event::Scroll(Scroll::PageUp)),
+            KeyCode::PageDown => Some(Event::Scroll(Scroll::PageDown)),
+            KeyCode::Tab => Some(Event::NextGroup),
+            KeyCode::BackTab => Some(Event::PreviousGroup),
+            // AltGr is reported as Control+Alt on some platforms, treat it as text
+            KeyCode::Char(c) if control && alt => Some(Event::Edit(Edit::Insert(c))),
+            KeyCode::Char(c) if control => Self::handle_control(c),
+            KeyCode::Char(c) if alt => Self::handle_alt(c),
+            KeyCode::Char(c) => Some(Event::Edit(Edit::Insert(c))),
+            _ => None,
+        }
+    }
+
+    /// Handle a key pressed together with Control
+    fn handle_control(c: char) -> Option<Event> {
+        match c {
+            'a' => Some(Event::Edit(Edit::Home)),
+            'e' => Some(Event::Edit(Edit::End)),
+            'w' => Some(Event::Edit(Edit::DeleteWordLeft)),
+            'u' => Some(Event::Edit(Edit::DeleteToStart)),
+            'k' => Some(Event::Edit(Edit::DeleteToEnd)),
+            'r' => Some(Event::SearchHistory),
+            _ => None,
+        }
+    }
+
+    /// Handle a key pressed together with Alt
+    fn handle_alt(c: char) -> Option<Event> {
+        match c {
+            'b' => Some(Event::Edit(Edit::WordLeft)),
+            'f' => Some(Event::Edit(Edit::WordRight)),
+            'd' => Some(Event::Edit(Edit::DeleteWordRight)),
+            // Alt+1 to Alt+9 select a group directly
+            '1'..='9' => Some(Event::SelectGroup(c as usize - '1' as usize)),
             _ => None,
         }
     }
@@ -82,6 +150,13 @@ impl EventHandler {
     }
 }
 
+impl Drop for EventHandler {
+    /// Stop reading the terminal, so the next handler gets every event
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
 impl Default for EventHandler {
     fn default() -> Self {
         Self::new()
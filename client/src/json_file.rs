@@ -0,0 +1,62 @@
+use std::{ffi::OsString, fs, path::PathBuf};
+
+use log::{debug, error};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A local file a value is kept in as JSON, so it survives restarts of the
+/// client. Nothing is read or saved if there is no path.
+#[derive(Default)]
+pub struct JsonFile {
+    path: Option<PathBuf>,
+    /// What the file holds, for the log
+    what: &'static str,
+}
+
+impl JsonFile {
+    pub fn new(path: Option<PathBuf>, what: &'static str) -> Self {
+        Self { path, what }
+    }
+
+    /// Read the value, the default if the file does not exist or is invalid
+    pub fn load<T: DeserializeOwned + Default>(&self) -> T {
+        match self.path.as_ref().map(fs::read_to_string) {
+            Some(Ok(content)) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                error!("Failed to parse the {}: {}", self.what, e);
+                T::default()
+            }),
+            _ => T::default(),
+        }
+    }
+
+    /// Write the value to a temporary file and move it over the file, so a
+    /// crash while writing can not leave a truncated file behind
+    pub fn save<T: Serialize>(&self, value: &T) {
+        let Some(ref path) = self.path else {
+            return;
+        };
+        if let Some(parent) = path.parent() {
+            if let Err(e) = fs::create_dir_all(parent) {
+                error!("Failed to create {}: {}", parent.display(), e);
+                return;
+            }
+        }
+        let content = match serde_json::to_string(value) {
+            Ok(content) => content,
+            Err(e) => {
+                error!("Failed to serialize the {}: {}", self.what, e);
+                return;
+            }
+        };
+
+        let mut temporary = OsString::from(path.as_os_str());
+        temporary.push(".tmp");
+        let temporary = PathBuf::from(temporary);
+        match fs::write(&temporary, content).and_then(|()| fs::rename(&temporary, path)) {
+            Ok(()) => debug!("Saved the {} to {}", self.what, path.display()),
+            Err(e) => {
+                error!("Failed to save the {}: {}", self.what, e);
+                fs::remove_file(&temporary).ok();
+            }
+        }
+    }
+}
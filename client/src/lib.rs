@@ -1,18 +1,88 @@
-use model::ChatMessage;
+use chat_protocol::ServerFrame;
 
 pub mod app;
+pub mod command;
+pub mod config;
+pub mod history;
 pub mod input;
+pub mod json_file;
+pub mod login;
 pub mod model;
+pub mod outbox;
+pub mod recent;
+pub mod reconnect;
+pub mod text_area;
 pub mod tui;
 pub mod websocket;
+pub mod wrap;
 
 pub enum Event {
-    Input(char),
+    /// Change the text in the input box
+    Edit(Edit),
     Refresh,
     Quit,
+    /// Leave the history search, dismiss the notices, or quit if there is
+    /// nothing to dismiss
+    Cancel,
+    /// Search the sent messages, or find an older match if already searching
+    SearchHistory,
+    /// The connection to the server was lost
     Restart,
-    Resize { width: u16, height: u16 },
+    /// Time to try reconnecting
+    Reconnect,
+    /// Time to send the messages the server asked to wait with
+    Flush,
+    Resize {
+        width: u16,
+        height: u16,
+    },
     Send,
+    /// Scroll the messages, loading older ones from the server at the top
+    Scroll(Scroll),
+    /// Show the next joined group
+    NextGroup,
+    /// Show the previous joined group
+    PreviousGroup,
+    /// Show the joined group with the given index
+    SelectGroup(usize),
+    Recieve(ServerFrame),
+}
+
+pub enum Scroll {
+    /// Towards older messages, by the given number of lines
+    Up(usize),
+    /// Towards newer messages, by the given number of lines
+    Down(usize),
+    PageUp,
+    PageDown,
+    /// To the oldest loaded message
+    Top,
+    /// To the newest message
+    Bottom,
+}
+
+pub enum Edit {
+    Insert(char),
+    /// Insert pasted text, which can contain newlines
+    Paste(String),
+    /// Delete the grapheme before the cursor
     Backspace,
-    ReciveMessage(ChatMessage),
+    /// Delete the grapheme after the cursor
+    Delete,
+    Left,
+    Right,
+    /// To the line above in a multi-line draft
+    Up,
+    /// To the line below in a multi-line draft
+    Down,
+    /// To the start of the line
+    Home,
+    /// To the end of the line
+    End,
+    WordLeft,
+    WordRight,
+    DeleteWordLeft,
+    DeleteWordRight,
+    DeleteToStart,
+    DeleteToEnd,
 }
@@ -0,0 +1,376 @@
+use chat_protocol::Auth;
+use futures_util::{select, FutureExt};
+
+use crate::{
+    app::{Application, Connected, Login},
+    config::{AuthMethod, Config},
+    input::EventHandler,
+    recent::{Recent, RecentServer},
+    text_area::TextArea,
+    tui::TUI,
+    websocket::{Heartbeat, TlsOptions},
+    Edit, Event,
+};
+
+static DEFAULT_SERVER: &str = "ws://127.0.0.1:9001";
+static DEFAULT_GROUP: &str = "general";
+
+/// Fields of the login screen, in the order they are shown
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Field {
+    Server,
+    Name,
+    Groups,
+    Method,
+    Password,
+    Repeat,
+}
+
+/// The login screen shown before connecting and after losing the connection
+pub struct Form {
+    pub server: TextArea,
+    pub name: TextArea,
+    /// Groups to join, separated by ','
+    pub groups: TextArea,
+    pub method: AuthMethod,
+    pub password: TextArea,
+    /// The password again, to catch typos when registering
+    pub repeat: TextArea,
+    pub focus: Field,
+    /// Session token of the last login, used instead of an empty password
+    pub token: Option<String>,
+    pub recent: Recent,
+    /// Why connecting failed
+    pub error: Option<String>,
+    /// Whether a connection attempt is running
+    pub connecting: bool,
+}
+
+/// What the form was filled in with
+pub struct Submission {
+    pub server: String,
+    pub login: Login,
+    pub groups: Vec<String>,
+}
+
+impl Form {
+    /// A form filled in with the settings from the config
+    pub fn new(config: &Config) -> Self {
+        let mut form = Self {
+            server: TextArea::default(),
+            name: TextArea::default(),
+            groups: TextArea::default(),
+            method: config.auth.unwrap_or_default(),
+            password: TextArea::default(),
+            repeat: TextArea::default(),
+            focus: Field::Server,
+            token: None,
+            recent: Recent::load(config.recent_file.clone()),
+            error: None,
+            connecting: false,
+        };
+
+        // Without a profile, start from the server used last
+        let last = form.recent.servers().first().cloned();
+        let server = config
+            .server
+            .clone()
+            .or_else(|| last.as_ref().map(|r| r.server.clone()));
+        form.server
+            .set(server.unwrap_or_else(|| DEFAULT_SERVER.to_string()));
+        if let Some(ref name) = config.name {
+            form.name.set(name.clone());
+        } else if let Some(ref last) = last {
+            form.name.set(last.name.clone());
+        }
+        let groups = match (&config.groups, &last) {
+            (Some(groups), _) => groups.join(", "),
+            (None, Some(last)) => last.groups.join(", "),
+            (None, None) => DEFAULT_GROUP.to_string(),
+        };
+        form.groups.set(groups);
+
+        form.focus = form.first_missing();
+        form
+    }
+
+    /// Whether everything needed to connect is known, so the form does not
+    /// have to be shown
+    pub fn is_complete(config: &Config) -> bool {
+        config.server.is_some()
+            && config.name.is_some()
+            && config.groups.is_some()
+            && matches!(config.auth, None | Some(AuthMethod::Guest))
+    }
+
+    /// The first field that needs to be filled in
+    fn first_missing(&self) -> Field {
+        self.fields()
+            .into_iter()
+            .find(|field| {
+                self.text_area(*field)
+                    .is_some_and(|text_area| text_area.is_blank())
+            })
+            .unwrap_or(Field::Name)
+    }
+
+    /// The fields shown for the chosen way to log in
+    pub fn fields(&self) -> Vec<Field> {
+        let mut fields = vec![Field::Server, Field::Name, Field::Groups, Field::Method];
+        match self.method {
+            AuthMethod::Guest => {}
+            AuthMethod::Login => fields.push(Field::Password),
+            AuthMethod::Register => fields.extend([Field::Password, Field::Repeat]),
+        }
+        fields
+    }
+
+    /// The text of a field, None for the login method
+    pub fn text_area(&self, field: Field) -> Option<&TextArea> {
+        match field {
+            Field::Server => Some(&self.server),
+            Field::Name => Some(&self.name),
+            Field::Groups => Some(&self.groups),
+            Field::Method => None,
+            Field::Password => Some(&self.password),
+            Field::Repeat => Some(&self.repeat),
+        }
+    }
+
+    fn text_area_mut(&mut self, field: Field) -> Option<&mut TextArea> {
+        match field {
+            Field::Server => Some(&mut self.server),
+            Field::Name => Some(&mut self.name),
+            Field::Groups => Some(&mut self.groups),
+            Field::Method => None,
+            Field::Password => Some(&mut self.password),
+            Field::Repeat => Some(&mut self.repeat),
+        }
+    }
+
+    /// Move the focus by `step` fields, wrapping around
+    pub fn move_focus(&mut self, step: isize) {
+        let fields = self.fields();
+        let current = fields.iter().position(|f| *f == self.focus).unwrap_or(0);
+        let next = (current as isize + step).rem_euclid(fields.len() as isize);
+        self.focus = fields[next as usize];
+    }
+
+    pub fn edit(&mut self, edit: Edit) {
+        match (self.focus, edit) {
+            (_, Edit::Up) => self.move_focus(-1),
+            (_, Edit::Down) => self.move_focus(1),
+            (Field::Method, Edit::Left) => self.change_method(-1),
+            (Field::Method, Edit::Right | Edit::Insert(' ')) => self.change_method(1),
+            (Field::Method, _) => {}
+            // Every field is a single line
+            (_, Edit::Insert('\n')) => {}
+            (focus, Edit::Paste(text)) => {
+                let text = text.lines().collect::<Vec<_>>().join(" ");
+                if let Some(text_area) = self.text_area_mut(focus) {
+                    text_area.edit(Edit::Paste(text));
+                }
+            }
+            (focus, edit) => {
+                if let Some(text_area) = self.text_area_mut(focus) {
+                    text_area.edit(edit);
+                }
+            }
+        }
+    }
+
+    fn change_method(&mut self, step: isize) {
+        let methods = [AuthMethod::Guest, AuthMethod::Login, AuthMethod::Register];
+        let current = methods.iter().position(|m| *m == self.method).unwrap_or(0);
+        let next = (current as isize + step).rem_euclid(methods.len() as isize);
+        self.method = methods[next as usize];
+    }
+
+    /// Fill in a server from the recent list
+    pub fn choose_recent(&mut self, index: usize) {
+        let Some(recent) = self.recent.servers().get(index).cloned() else {
+            return;
+        };
+        // A token belongs to the account and server it was issued for
+        if self.name.text() != recent.name || self.server.text() != recent.server {
+            self.token = None;
+        }
+        self.server.set(recent.server);
+        self.name.set(recent.name);
+        self.groups.set(recent.groups.join(", "));
+        self.method = match recent.account {
+            true => AuthMethod::Login,
+            false => AuthMethod::Guest,
+        };
+        self.error = None;
+        self.focus = match recent.account && self.token.is_none() {
+            true => Field::Password,
+            false => Field::Name,
+        };
+    }
+
+    /// Check the fields, on failure the focus moves to the field with the problem
+    pub fn submit(&mut self) -> Result<Submission, String> {
+        let server = self.server.text().trim().to_string();
+        if !(server.starts_with("ws://") || server.starts_with("wss://")) {
+            self.focus = Field::Server;
+            return Err("The server has to start with ws:// or wss://".to_string());
+        }
+        let name = self.name.text().trim().to_string();
+        if name.is_empty() {
+            self.focus = Field::Name;
+            return Err("Enter a name".to_string());
+        }
+        let groups: Vec<String> = self
+            .groups
+            .text()
+            .split(',')
+            .map(|g| g.trim().to_string())
+            .filter(|g| !g.is_empty())
+            .collect();
+        if groups.is_empty() {
+            self.focus = Field::Groups;
+            return Err("Enter at least one group".to_string());
+        }
+
+        let password = self.password.text().to_string();
+        let auth = match self.method {
+            AuthMethod::Guest => Auth::Guest,
+            AuthMethod::Login if password.is_empty() => match self.token {
+                Some(ref token) => Auth::Token {
+                    token: token.clone(),
+                },
+                None => {
+                    self.focus = Field::Password;
+                    return Err("Enter your password".to_string());
+                }
+            },
+            AuthMethod::Login => Auth::Password { password },
+            AuthMethod::Register if password.is_empty() => {
+                self.focus = Field::Password;
+                return Err("Choose a password".to_string());
+            }
+            AuthMethod::Register if self.repeat.text() != password => {
+                self.focus = Field::Repeat;
+                return Err("The passwords do not match".to_string());
+            }
+            AuthMethod::Register => Auth::Register { password },
+        };
+
+        Ok(Submission {
+            server,
+            login: Login {
+                username: name,
+                auth,
+            },
+            groups,
+        })
+    }
+
+    /// Remember a successful login, the password is not needed again
+    fn connected(&mut self, connected: &Connected) {
+        if let Auth::Token { ref token } = connected.login.auth {
+            self.token = Some(token.clone());
+            self.method = AuthMethod::Login;
+        }
+        self.password.take();
+        self.repeat.take();
+        self.error = None;
+        self.recent.push(RecentServer {
+            server: connected.server.clone(),
+            name: connected.login.username.clone(),
+            groups: connected.groups.clone(),
+            account: self.method == AuthMethod::Login,
+        });
+    }
+
+    /// Show the form again after the server refused to log in again
+    pub fn rejected(&mut self, login: Login, reason: String) {
+        // The name could have been changed with /nick
+        self.name.set(login.username);
+        // The session is no longer accepted, the password is needed
+        self.token = None;
+        self.focus = match self.method {
+            AuthMethod::Guest => Field::Name,
+            AuthMethod::Login | AuthMethod::Register => Field::Password,
+        };
+        self.error = Some(reason);
+    }
+}
+
+/// Show the login screen until connecting succeeds. Connects right away if
+/// `connect` is set. Returns None if the user quit.
+pub async fn run(
+    form: &mut Form,
+    tls: &TlsOptions,
+    heartbeat: Heartbeat,
+    tui: &mut TUI,
+    mut connect: bool,
+) -> Option<Connected> {
+    let mut input = EventHandler::new();
+    tui.terminal.clear().ok()?;
+
+    loop {
+        if connect {
+            connect = false;
+            match form.submit() {
+                Ok(submission) => {
+                    let used_token = matches!(submission.login.auth, Auth::Token { .. });
+                    form.error = None;
+                    form.connecting = true;
+                    tui.render_form(form).ok();
+
+                    let result = select! {
+                        result = Application::connect(submission, tls, heartbeat).fuse() => Some(result),
+                        // Any key cancels, Ctrl+C quits
+                        event = next_key(&mut input).fuse() => match event {
+                            Event::Quit => return None,
+                            _ => None,
+                        },
+                    };
+                    form.connecting = false;
+                    match result {
+                        Some(Ok(connected)) => {
+                            form.connected(&connected);
+                            return Some(connected);
+                        }
+                        Some(Err(e)) => {
+                            // A rejected token has to be replaced by the password
+                            if used_token && e.rejected && !e.transient {
+                                form.token = None;
+                                form.focus = Field::Password;
+                            }
+                            form.error = Some(e.message);
+                        }
+                        None => form.error = Some("Cancelled".to_string()),
+                    }
+                }
+                Err(problem) => form.error = Some(problem),
+            }
+        }
+
+        tui.render_form(form).ok();
+        match input.next().await {
+            Event::Quit => return None,
+            Event::Cancel if form.error.is_some() => form.error = None,
+            Event::Cancel => return None,
+            Event::Send => connect = true,
+            Event::NextGroup => form.move_focus(1),
+            Event::PreviousGroup => form.move_focus(-1),
+            Event::SelectGroup(index) => form.choose_recent(index),
+            Event::Edit(edit) => form.edit(edit),
+            Event::Resize { width, height } => tui.resize(width, height),
+            _ => {}
+        }
+    }
+}
+
+/// Wait for a key while connecting, ignoring resizes
+async fn next_key(input: &mut EventHandler) -> Event {
+    loop {
+        match input.next().await {
+            Event::Resize { .. } | Event::Scroll(_) => continue,
+            event => return event,
+        }
+    }
+}
@@ -1,5 +1,11 @@
-use chat_client::{app::Application, tui::TUI};
-use log::{error, info};
+use chat_client::{
+    app::Application,
+    config::{Args, Config},
+    login::{self, Form},
+    tui::TUI,
+};
+use clap::Parser;
+use log::info;
 
 #[tokio::main]
 async fn main() {
@@ -8,38 +14,32 @@ async fn main() {
     // Setup env_logger to write to stderr
     env_logger::init();
 
-    // Get Name
-    let mut name = String::new();
-    println!("Enter your name: ");
-    std::io::stdin().read_line(&mut name).unwrap();
-    let name = match name.trim() {
-        "" => "Anonymous",
-        name => name,
+    let config = match Config::load(Args::parse()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{:#}", e);
+            std::process::exit(2);
+        }
     };
 
-    // Get Group to join
-    let mut group = String::new();
-    println!("Which group chat do you want to join (leave empty for default value 'general'): ");
-    std::io::stdin().read_line(&mut group).unwrap();
-    let group = match group.trim() {
-        "" => "general",
-        group => group,
-    };
+    let mut tui = TUI::new();
+    TUI::initialize_panic_handler();
+    tui.enter().expect("Failed to set up terminal");
 
-    // Get Server Address
-    let mut address = String::new();
-    println!(
-        "Enter server address and port (leave empty for default value 'ws://127.0.0.1:9001'): "
-    );
-    std::io::stdin().read_line(&mut address).unwrap();
-    let address = match address.trim() {
-        "" => "ws://127.0.0.1:9001",
-        address => address,
-    };
+    // Skip the login screen if the profile or arguments say everything
+    let mut form = Form::new(&config);
+    let mut connect = Form::is_complete(&config);
 
-    // Run until the application returns false
-    while Application::new(address, name, group).run().await {
-        error!("Server Disconnected, press enter to try to reconnect");
+    // Show the login screen until the user quits, and again if the server
+    // refuses to let the client back in after reconnecting
+    while let Some(connected) =
+        login::run(&mut form, &config.tls, config.heartbeat, &mut tui, connect).await
+    {
+        match Application::new(connected, &config).run().await {
+            Some((login, reason)) => form.rejected(login, reason),
+            None => break,
+        }
+        connect = false;
     }
 
     TUI::exit().expect("Failed to reset terminal");
@@ -1,27 +1,348 @@
+use std::time::{Duration, Instant};
+
+pub use chat_protocol::ChatMessage;
+
+use crate::{history::History, outbox::Outbox, text_area::TextArea, Edit};
+
+/// How many notices are kept above the input box
+static MAX_NOTICES: usize = 10;
+
+/// strftime format of the time shown in front of every message
+pub static DEFAULT_TIME_FORMAT: &str = "%H:%M";
+/// strftime format of the date shown when the day changes
+pub static DEFAULT_DATE_FORMAT: &str = "%A, %d %B %Y";
+
 #[derive(Default)]
 pub struct Model {
     pub url: String,
     pub username: String,
-    pub text_area: String,
-    pub group: String,
+    pub text_area: TextArea,
+    pub time_format: String,
+    pub date_format: String,
+    /// Groups joined on the server, in the order they were joined
+    pub groups: Vec<Group>,
+    /// Index of the group shown in the UI
+    pub active: usize,
+    /// Messages sent by the user
+    pub history: History,
+    /// Messages the server did not confirm yet, shown below the others
+    pub outbox: Outbox,
+    /// Index of the history entry shown in the input box
+    pub recall: Option<usize>,
+    /// Search through the history started with Ctrl+R
+    pub search: Option<Search>,
+    /// Lines shown above the input box until the next message is sent
+    pub notices: Vec<Notice>,
+    pub connection: ConnectionState,
+    /// Round trip time to the server, measured with pings
+    pub latency: Option<Duration>,
+}
+
+/// State of the connection to the server, shown in the status bar
+#[derive(Default, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    #[default]
+    Connected,
+    /// Waiting until the next attempt to reconnect
+    Reconnecting { at: Instant },
+    /// Trying to reconnect right now
+    Connecting,
+    /// Gave up reconnecting, `/reconnect` tries again
+    Offline,
+}
+
+/// Feedback about commands and errors reported by the server
+pub struct Notice {
+    pub text: String,
+    pub error: bool,
+}
+
+/// Reverse incremental search through the sent messages of the shown group
+#[derive(Default)]
+pub struct Search {
+    pub query: String,
+    /// Index of the newest history entry containing the query
+    pub found: Option<usize>,
+}
+
+pub struct Group {
+    pub name: String,
     pub messages: Vec<ChatMessage>,
+    /// Messages recieved while the group was not shown
+    pub unread: usize,
+    /// How many lines the view is scrolled up from the newest message
+    pub scroll: usize,
+    /// Messages recieved while scrolled up
+    pub new_below: usize,
+    /// Whether the server has messages older than the oldest one recieved
+    pub has_more: bool,
+    /// Whether older messages were requested and did not arrive yet
+    pub loading_older: bool,
 }
 
-pub struct ChatMessage {
-    pub username: String,
-    pub message: String,
+impl Group {
+    /// Index a new message goes to, in order of the ids. A missed message can
+    /// arrive after a newer one that was sent live.
+    pub fn position(&self, message: &ChatMessage) -> usize {
+        match message.id {
+            Some(_) => self
+                .messages
+                .iter()
+                .rposition(|m| m.id <= message.id)
+                .map_or(0, |i| i + 1),
+            None => self.messages.len(),
+        }
+    }
 }
 
-impl ChatMessage {
-    pub fn serialize(&self) -> String {
-        format!("{}: {}", self.username, self.message)
+impl Model {
+    pub fn group(&self, name: &str) -> Option<&Group> {
+        self.groups.iter().find(|g| g.name == name)
+    }
+
+    pub fn group_mut(&mut self, name: &str) -> Option<&mut Group> {
+        self.groups.iter_mut().find(|g| g.name == name)
+    }
+
+    /// The group currently shown in the UI
+    pub fn active_group(&self) -> Option<&Group> {
+        self.groups.get(self.active)
+    }
+
+    /// Show the group at the given index and mark it as read
+    pub fn select(&mut self, index: usize) {
+        if let Some(group) = self.groups.get_mut(index) {
+            group.unread = 0;
+            self.active = index;
+        }
+        // The history is per group
+        self.recall = None;
+        self.search = None;
+    }
+
+    /// Show an informational line above the input box
+    pub fn notice(&mut self, text: impl Into<String>) {
+        self.push_notice(text.into(), false);
+    }
+
+    /// Show an error above the input box
+    pub fn error(&mut self, text: impl Into<String>) {
+        self.push_notice(text.into(), true);
+    }
+
+    fn push_notice(&mut self, text: String, error: bool) {
+        self.notices.push(Notice { text, error });
+        if self.notices.len() > MAX_NOTICES {
+            self.notices.remove(0);
+        }
+    }
+
+    /// Remove the loaded messages of the shown group from the screen
+    pub fn clear(&mut self) {
+        if let Some(group) = self.groups.get_mut(self.active) {
+            group.messages.clear();
+            group.scroll = 0;
+            group.new_below = 0;
+            // Do not load the cleared messages again when scrolling up
+            group.has_more = false;
+        }
+    }
+
+    /// Sent messages of the shown group, oldest first
+    fn history_entries(&self) -> &[String] {
+        match self.active_group() {
+            Some(group) => self.history.entries(&group.name),
+            None => &[],
+        }
+    }
+
+    /// Show the previous sent message in the input box. Returns false if the
+    /// input holds a draft instead of an empty or recalled message.
+    pub fn recall_older(&mut self) -> bool {
+        let index = match self.recall {
+            Some(index) => index.saturating_sub(1),
+            None if self.text_area.is_empty() && !self.history_entries().is_empty() => {
+                self.history_entries().len() - 1
+            }
+            None => return false,
+        };
+        self.recall = Some(index);
+        self.text_area.set(self.history_entries()[index].clone());
+        true
+    }
+
+    /// Show the next sent message in the input box, or empty it after the
+    /// newest one. Returns false if no message was recalled.
+    pub fn recall_newer(&mut self) -> bool {
+        let Some(index) = self.recall else {
+            return false;
+        };
+        if index + 1 < self.history_entries().len() {
+            self.recall = Some(index + 1);
+            self.text_area
+                .set(self.history_entries()[index + 1].clone());
+        } else {
+            self.recall = None;
+            self.text_area.take();
+        }
+        true
+    }
+
+    /// Start searching the history, or look for an older match if already
+    /// searching
+    pub fn search_older(&mut self) {
+        match self.search {
+            Some(ref search) => {
+                let before = search.found.unwrap_or(0);
+                self.find(before);
+            }
+            None => self.search = Some(Search::default()),
+        }
+    }
+
+    /// Change the search query, any other edit takes the found message into
+    /// the input box and applies the edit to it
+    pub fn search_edit(&mut self, edit: Edit) {
+        let Some(ref mut search) = self.search else {
+            return;
+        };
+        match edit {
+            Edit::Insert(c) => search.query.push(c),
+            Edit::Backspace => {
+                search.query.pop();
+            }
+            edit => {
+                self.accept_search();
+                self.text_area.edit(edit);
+                return;
+            }
+        }
+        let newest = self.history_entries().len();
+        self.find(newest);
+    }
+
+    /// Find the newest entry before `before` that contains the query
+    fn find(&mut self, before: usize) {
+        let entries = match self.active_group() {
+            Some(group) => self.history.entries(&group.name),
+            None => &[],
+        };
+        if let Some(ref mut search) = self.search {
+            if search.query.is_empty() {
+                search.found = None;
+            } else if let Some(index) = entries[..before]
+                .iter()
+                .rposition(|e| e.contains(&search.query))
+            {
+                search.found = Some(index);
+            } else if before == entries.len() {
+                // Nothing matches the changed query
+                search.found = None;
+            }
+        }
+    }
+
+    /// End the search and put the found message into the input box
+    pub fn accept_search(&mut self) {
+        if let Some(index) = self.search.take().and_then(|s| s.found) {
+            self.recall = Some(index);
+            self.text_area.set(self.history_entries()[index].clone());
+        }
+    }
+
+    /// Show the next group, wrapping around at the end
+    pub fn next_group(&mut self) {
+        if !self.groups.is_empty() {
+            self.select((self.active + 1) % self.groups.len());
+        }
+    }
+
+    /// Show the previous group, wrapping around at the start
+    pub fn previous_group(&mut self) {
+        if !self.groups.is_empty() {
+            self.select((self.active + self.groups.len() - 1) % self.groups.len());
+        }
+    }
+
+    /// Add a message taking up `lines` lines to a group, counting it as unread
+    /// if the group is not shown. Returns false if the group is unknown.
+    pub fn push_message(&mut self, name: &str, message: ChatMessage, lines: usize) -> bool {
+        let active = self.active;
+        match self
+            .groups
+            .iter_mut()
+            .enumerate()
+            .find(|(_, g)| g.name == name)
+        {
+            Some((index, group)) => {
+                // Messages can arrive twice while resuming after a reconnect
+                if message.id.is_some() && group.messages.iter().rev().any(|m| m.id == message.id) {
+                    return true;
+                }
+                let position = group.position(&message);
+                group.messages.insert(position, message);
+                if index != active {
+                    group.unread += 1;
+                }
+                // Keep the view in place while scrolled up
+                if group.scroll > 0 {
+                    group.scroll += lines;
+                    group.new_below += 1;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Scroll the shown group up by `amount` lines, but at most to `top`. Returns
+    /// the group and the id of its oldest message if the top was reached and
+    /// older messages should be loaded.
+    pub fn scroll_up(&mut self, amount: usize, top: usize) -> Option<(String, i64)> {
+        let group = self.groups.get_mut(self.active)?;
+        group.scroll = group.scroll.saturating_add(amount).min(top);
+
+        if group.scroll == top && group.has_more && !group.loading_older {
+            let oldest = group.messages.first().and_then(|m| m.id)?;
+            group.loading_older = true;
+            return Some((group.name.clone(), oldest));
+        }
+        None
+    }
+
+    /// Scroll the shown group down by `amount` lines
+    pub fn scroll_down(&mut self, amount: usize) {
+        if let Some(group) = self.groups.get_mut(self.active) {
+            group.scroll = group.scroll.saturating_sub(amount);
+            if group.scroll == 0 {
+                group.new_below = 0;
+            }
+        }
+    }
+
+    /// Add a group if it was not joined yet
+    pub fn join(&mut self, name: &str) {
+        if self.group(name).is_none() {
+            self.groups.push(Group {
+                name: name.to_string(),
+                messages: Vec::new(),
+                unread: 0,
+                scroll: 0,
+                new_below: 0,
+                has_more: false,
+                loading_older: false,
+            });
+        }
     }
 
-    // Security issue: a username can contain ": ", which would break the deserialization
-    pub fn deserialize(s: &str) -> Option<Self> {
-        let mut parts = s.splitn(2, ": ");
-        let username = parts.next()?.to_string();
-        let message = parts.next()?.to_string();
-        Some(Self { username, message })
+    /// Remove a group and keep the active index in bounds
+    pub fn part(&mut self, name: &str) {
+        if let Some(index) = self.groups.iter().position(|g| g.name == name) {
+            self.groups.remove(index);
+            if self.active > index || self.active >= self.groups.len() {
+                self.active = self.active.saturating_sub(1);
+            }
+            self.select(self.active);
+        }
     }
 }
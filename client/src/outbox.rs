@@ -0,0 +1,237 @@
+use std::path::PathBuf;
+
+use chat_protocol::{ClientFrame, RejectReason};
+use serde::{Deserialize, Serialize};
+
+use crate::json_file::JsonFile;
+
+/// A message the server did not confirm yet
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Pending {
+    /// Idempotency key, so sending it again does not store it twice
+    pub key: String,
+    pub server: String,
+    pub group: String,
+    pub message: String,
+    #[serde(default)]
+    pub action: bool,
+    /// Not saved, every message is sent again after restarting
+    #[serde(skip)]
+    pub state: State,
+}
+
+/// Where a message in the outbox is on its way to the server
+#[derive(Clone, Default, PartialEq, Eq)]
+pub enum State {
+    /// Waiting for a connection
+    #[default]
+    Queued,
+    /// Sent over the current connection, the server did not answer yet
+    Sending,
+    /// Refused by the server, only sent again when retried
+    Failed(RejectReason),
+}
+
+impl Pending {
+    /// The frame sending the message
+    pub fn frame(&self) -> ClientFrame {
+        ClientFrame::Chat {
+            group: self.group.clone(),
+            message: self.message.clone(),
+            action: self.action,
+            key: Some(self.key.clone()),
+        }
+    }
+}
+
+/// Messages waiting to be sent or confirmed, oldest first, kept in a local
+/// file so they survive restarts of the client
+#[derive(Default)]
+pub struct Outbox {
+    file: JsonFile,
+    messages: Vec<Pending>,
+}
+
+impl Outbox {
+    /// Load the outbox from a file, it is saved there as well
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let file = JsonFile::new(path, "outbox");
+        Self {
+            messages: file.load(),
+            file,
+        }
+    }
+
+    /// Pending messages to a group on a server, oldest first
+    pub fn pending<'a>(
+        &'a self,
+        server: &'a str,
+        group: &'a str,
+    ) -> impl Iterator<Item = &'a Pending> + 'a {
+        self.messages
+            .iter()
+            .filter(move |p| p.server == server && p.group == group)
+    }
+
+    /// Queue a message with a new key and save the outbox
+    pub fn push(&mut self, server: &str, group: &str, message: String, action: bool) {
+        self.messages.push(Pending {
+            key: new_key(),
+            server: server.to_string(),
+            group: group.to_string(),
+            message,
+            action,
+            state: State::Queued,
+        });
+        self.file.save(&self.messages);
+    }
+
+    /// Mark the oldest queued message of a group as sending and return the
+    /// frame to send it with. Messages are sent one at a time, so they arrive
+    /// in order even if the server asks to wait with some, None while one is
+    /// still sending.
+    pub fn next(&mut self, server: &str, group: &str) -> Option<ClientFrame> {
+        let mut pending = self
+            .messages
+            .iter_mut()
+            .filter(|p| p.server == server && p.group == group);
+        let next = pending.find(|p| matches!(p.state, State::Queued | State::Sending))?;
+        if next.state == State::Sending {
+            return None;
+        }
+        next.state = State::Sending;
+        Some(next.frame())
+    }
+
+    /// Queue a message again to send it later
+    pub fn requeue(&mut self, key: &str) {
+        if let Some(pending) = self.messages.iter_mut().find(|p| p.key == key) {
+            pending.state = State::Queued;
+        }
+    }
+
+    /// Queue the messages that were sending when the connection was lost
+    pub fn disconnected(&mut self) {
+        for pending in &mut self.messages {
+            if pending.state == State::Sending {
+                pending.state = State::Queued;
+            }
+        }
+    }
+
+    /// Mark a message as refused by the server
+    pub fn fail(&mut self, key: &str, reason: RejectReason) {
+        if let Some(pending) = self.messages.iter_mut().find(|p| p.key == key) {
+            pending.state = State::Failed(reason);
+        }
+    }
+
+    /// Queue the failed messages of a group again, returns how many there were
+    pub fn retry(&mut self, server: &str, group: &str) -> usize {
+        let mut count = 0;
+        for pending in &mut self.messages {
+            if pending.server == server
+                && pending.group == group
+                && matches!(pending.state, State::Failed(_))
+            {
+                pending.state = State::Queued;
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Drop the failed messages of a group, returns how many there were
+    pub fn discard(&mut self, server: &str, group: &str) -> usize {
+        let before = self.messages.len();
+        self.messages.retain(|p| {
+            !(p.server == server && p.group == group && matches!(p.state, State::Failed(_)))
+        });
+        let count = before - self.messages.len();
+        if count > 0 {
+            self.file.save(&self.messages);
+        }
+        count
+    }
+
+    /// Forget the message with the given key once the server has it.
+    /// Returns false if there is no such message.
+    pub fn remove(&mut self, key: &str) -> bool {
+        let Some(index) = self.messages.iter().position(|p| p.key == key) else {
+            return false;
+        };
+        self.messages.remove(index);
+        self.file.save(&self.messages);
+        true
+    }
+}
+
+/// A random key, unlikely to ever be chosen twice
+fn new_key() -> String {
+    format!("{:016x}{:016x}", fastrand::u64(..), fastrand::u64(..))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(frame: Option<ClientFrame>) -> Option<String> {
+        match frame? {
+            ClientFrame::Chat { message, .. } => Some(message),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn sends_one_message_at_a_time() {
+        let mut outbox = Outbox::load(None);
+        outbox.push("server", "lobby", "one".to_string(), false);
+        outbox.push("server", "lobby", "two".to_string(), false);
+        outbox.push("server", "other", "three".to_string(), false);
+
+        assert_eq!(
+            message(outbox.next("server", "lobby")).as_deref(),
+            Some("one")
+        );
+        assert_eq!(outbox.next("server", "lobby"), None);
+        // Other groups do not wait for it
+        assert_eq!(
+            message(outbox.next("server", "other")).as_deref(),
+            Some("three")
+        );
+
+        let key = outbox
+            .pending("server", "lobby")
+            .next()
+            .unwrap()
+            .key
+            .clone();
+        assert!(outbox.remove(&key));
+        assert_eq!(
+            message(outbox.next("server", "lobby")).as_deref(),
+            Some("two")
+        );
+    }
+
+    #[test]
+    fn requeued_messages_are_sent_again() {
+        let mut outbox = Outbox::load(None);
+        outbox.push("server", "lobby", "one".to_string(), false);
+        outbox.push("server", "lobby", "two".to_string(), false);
+        outbox.next("server", "lobby");
+
+        let key = outbox
+            .pending("server", "lobby")
+            .next()
+            .unwrap()
+            .key
+            .clone();
+        outbox.requeue(&key);
+        let pending = outbox.pending("server", "lobby").next().unwrap();
+        assert!(pending.state == State::Queued);
+        assert_eq!(
+            message(outbox.next("server", "lobby")).as_deref(),
+            Some("one")
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::json_file::JsonFile;
+
+/// How many servers the login screen offers
+pub static MAX_RECENT: usize = 9;
+
+/// A server the client connected to, with the name and groups used
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct RecentServer {
+    pub server: String,
+    pub name: String,
+    pub groups: Vec<String>,
+    /// Whether the name was logged into an account
+    #[serde(default)]
+    pub account: bool,
+}
+
+/// Servers the client connected to, newest first, kept in a local file
+#[derive(Default)]
+pub struct Recent {
+    file: JsonFile,
+    servers: Vec<RecentServer>,
+}
+
+impl Recent {
+    /// Load the list from a file, it is saved there as well
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let file = JsonFile::new(path, "recent servers");
+        Self {
+            servers: file.load(),
+            file,
+        }
+    }
+
+    /// The servers, newest first
+    pub fn servers(&self) -> &[RecentServer] {
+        &self.servers
+    }
+
+    /// Move a server to the front of the list and save it
+    pub fn push(&mut self, server: RecentServer) {
+        self.servers
+            .retain(|s| !(s.server == server.server && s.name == server.name));
+        self.servers.insert(0, server);
+        self.servers.truncate(MAX_RECENT);
+        self.file.save(&self.servers);
+    }
+}
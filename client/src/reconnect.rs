@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+/// Delay before the first attempt to reconnect
+static BASE_DELAY: Duration = Duration::from_secs(1);
+/// Longest delay between two attempts
+static MAX_DELAY: Duration = Duration::from_secs(30);
+/// Attempts before giving up until the user asks to reconnect
+static MAX_ATTEMPTS: u32 = 10;
+
+/// Exponential backoff with jitter between attempts to reconnect, so clients
+/// that lost the connection at the same time do not all return at once
+#[derive(Default)]
+pub struct Backoff {
+    attempt: u32,
+}
+
+impl Backoff {
+    /// How long to wait before the next attempt, None after too many attempts
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if self.attempt >= MAX_ATTEMPTS {
+            return None;
+        }
+        let delay = BASE_DELAY
+            .saturating_mul(1 << self.attempt.min(16))
+            .min(MAX_DELAY);
+        self.attempt += 1;
+        // Somewhere between half and the full delay
+        Some(delay.mul_f64(0.5 + fastrand::f64() / 2.0))
+    }
+
+    /// Start over after connecting
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
@@ -0,0 +1,246 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::Edit;
+
+/// How many spaces a pasted tab is replaced with
+static TAB_WIDTH: usize = 4;
+
+/// The text typed in the input box and the position of the cursor in it.
+/// The text can span multiple lines separated by `\n`.
+#[derive(Default)]
+pub struct TextArea {
+    text: String,
+    /// Byte offset of the cursor, always at the start of a grapheme
+    cursor: usize,
+}
+
+impl TextArea {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Byte offset of the cursor in the text
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Replace the text and move the cursor to its end
+    pub fn set(&mut self, text: String) {
+        self.cursor = text.len();
+        self.text = text;
+    }
+
+    /// Whether there is nothing but whitespace to send
+    pub fn is_blank(&self) -> bool {
+        self.text.trim().is_empty()
+    }
+
+    /// Number of lines in the text
+    pub fn line_count(&self) -> usize {
+        self.text.split('\n').count()
+    }
+
+    /// Line of the cursor and its display column in that line
+    pub fn cursor_position(&self) -> (usize, usize) {
+        let before = &self.text[..self.cursor];
+        let row = before.matches('\n').count();
+        (row, before[self.line_start()..].width())
+    }
+
+    /// Remove the text and return it
+    pub fn take(&mut self) -> String {
+        self.cursor = 0;
+        std::mem::take(&mut self.text)
+    }
+
+    pub fn edit(&mut self, edit: Edit) {
+        match edit {
+            Edit::Insert(c) => {
+                self.text.insert(self.cursor, c);
+                self.cursor += c.len_utf8();
+            }
+            Edit::Backspace => self.delete_to(self.previous_grapheme()),
+            Edit::Delete => self.delete_to(self.next_grapheme()),
+            Edit::Left => self.cursor = self.previous_grapheme(),
+            Edit::Right => self.cursor = self.next_grapheme(),
+            Edit::Paste(text) => {
+                let text = text
+                    .replace("\r\n", "\n")
+                    .replace('\r', "\n")
+                    .replace('\t', &" ".repeat(TAB_WIDTH));
+                self.text.insert_str(self.cursor, &text);
+                self.cursor += text.len();
+            }
+            Edit::Up => self.cursor = self.vertical(false),
+            Edit::Down => self.cursor = self.vertical(true),
+            Edit::Home => self.cursor = self.line_start(),
+            Edit::End => self.cursor = self.line_end(),
+            Edit::WordLeft => self.cursor = self.previous_word(),
+            Edit::WordRight => self.cursor = self.next_word(),
+            Edit::DeleteWordLeft => self.delete_to(self.previous_word()),
+            Edit::DeleteWordRight => self.delete_to(self.next_word()),
+            Edit::DeleteToStart => self.delete_to(self.line_start()),
+            Edit::DeleteToEnd => self.delete_to(self.line_end()),
+        }
+    }
+
+    /// Delete the text between the cursor and `offset`, leaving the cursor
+    /// at the start of the deleted range
+    fn delete_to(&mut self, offset: usize) {
+        let range = self.cursor.min(offset)..self.cursor.max(offset);
+        self.cursor = range.start;
+        self.text.replace_range(range, "");
+    }
+
+    /// Offset of the start of the line the cursor is in
+    fn line_start(&self) -> usize {
+        self.text[..self.cursor].rfind('\n').map_or(0, |i| i + 1)
+    }
+
+    /// Offset of the end of the line the cursor is in
+    fn line_end(&self) -> usize {
+        self.text[self.cursor..]
+            .find('\n')
+            .map_or(self.text.len(), |i| self.cursor + i)
+    }
+
+    /// Offset in the next or previous line at the same display column as the
+    /// cursor, or the end of that line if it is shorter
+    fn vertical(&self, down: bool) -> usize {
+        let start = if down {
+            match self.text[self.cursor..].find('\n') {
+                Some(i) => self.cursor + i + 1,
+                None => return self.cursor,
+            }
+        } else {
+            match self.line_start() {
+                0 => return self.cursor,
+                start => self.text[..start - 1].rfind('\n').map_or(0, |i| i + 1),
+            }
+        };
+
+        let column = self.text[self.line_start()..self.cursor].width();
+        let mut offset = start;
+        let mut width = 0;
+        for g in self.text[start..].graphemes(true) {
+            if g == "\n" || width + g.width() > column {
+                break;
+            }
+            width += g.width();
+            offset += g.len();
+        }
+        offset
+    }
+
+    /// Offset of the grapheme before the cursor
+    fn previous_grapheme(&self) -> usize {
+        self.text[..self.cursor]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    /// Offset of the grapheme after the cursor
+    fn next_grapheme(&self) -> usize {
+        self.text[self.cursor..]
+            .graphemes(true)
+            .next()
+            .map_or(self.cursor, |g| self.cursor + g.len())
+    }
+
+    /// Offset of the start of the word before the cursor
+    fn previous_word(&self) -> usize {
+        let mut offset = self.cursor;
+        // Skip whitespace, then the word
+        let mut in_word = false;
+        for (i, g) in self.text[..self.cursor].grapheme_indices(true).rev() {
+            let is_whitespace = g.chars().all(char::is_whitespace);
+            if in_word && is_whitespace {
+                break;
+            }
+            in_word |= !is_whitespace;
+            offset = i;
+        }
+        offset
+    }
+
+    /// Offset of the end of the word after the cursor
+    fn next_word(&self) -> usize {
+        let mut offset = self.cursor;
+        // Skip whitespace, then the word
+        let mut in_word = false;
+        for g in self.text[self.cursor..].graphemes(true) {
+            let is_whitespace = g.chars().all(char::is_whitespace);
+            if in_word && is_whitespace {
+                break;
+            }
+            in_word |= !is_whitespace;
+            offset += g.len();
+        }
+        offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(text: &str, cursor: usize) -> TextArea {
+        TextArea {
+            text: text.to_string(),
+            cursor,
+        }
+    }
+
+    #[test]
+    fn moves_by_word() {
+        let mut input = area("one  two three", 0);
+        input.edit(Edit::WordRight);
+        assert_eq!(input.cursor(), 3);
+        input.edit(Edit::WordRight);
+        assert_eq!(input.cursor(), 8);
+        input.edit(Edit::WordLeft);
+        assert_eq!(input.cursor(), 5);
+        input.edit(Edit::WordLeft);
+        assert_eq!(input.cursor(), 0);
+    }
+
+    #[test]
+    fn deletes_the_word_before_the_cursor() {
+        let mut input = area("one two  ", 9);
+        input.edit(Edit::DeleteWordLeft);
+        assert_eq!(input.text(), "one ");
+        assert_eq!(input.cursor(), 4);
+    }
+
+    #[test]
+    fn keeps_the_column_between_lines() {
+        let mut input = area("abcdef\nab\nabcdef", 4);
+        input.edit(Edit::Down);
+        // The second line is shorter, the cursor stops at its end
+        assert_eq!(input.cursor_position(), (1, 2));
+        input.edit(Edit::Down);
+        assert_eq!(input.cursor_position(), (2, 2));
+        input.edit(Edit::Down);
+        assert_eq!(input.cursor_position(), (2, 2));
+        input.edit(Edit::Up);
+        input.edit(Edit::Up);
+        assert_eq!(input.cursor_position(), (0, 2));
+    }
+
+    #[test]
+    fn moves_vertically_by_display_column() {
+        // The cursor after the wide character is at column 2
+        let mut input = area("日x\nabc", "日".len());
+        input.edit(Edit::Down);
+        assert_eq!(input.cursor_position(), (1, 2));
+        input.edit(Edit::Right);
+        input.edit(Edit::Up);
+        assert_eq!(input.cursor_position(), (0, 3));
+    }
+}
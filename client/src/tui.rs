@@ -1,24 +1,64 @@
+use chrono::{DateTime, Local, NaiveDate, Utc};
 use crossterm::{
+    event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture},
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     ExecutableCommand,
 };
 use ratatui::{
-    layout::{Constraint, Direction, Layout, Rect},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     prelude::{CrosstermBackend, Stylize, Terminal as RatatuiTerminal},
-    symbols::block,
     text::{Line, Span},
-    widgets::{Block, Borders, Paragraph, Wrap},
+    widgets::{Block, Borders, Paragraph, Tabs},
     Frame,
 };
-use std::io::{stdout, Result, Stdout};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
-use crate::model::Model;
+use std::{
+    fmt::Write,
+    io::{stdout, Result, Stdout},
+    time::Instant,
+};
+
+use crate::{
+    config::AuthMethod,
+    login::{Field, Form},
+    model::{ChatMessage, ConnectionState, Group, Model, DEFAULT_DATE_FORMAT, DEFAULT_TIME_FORMAT},
+    outbox::{Pending, State},
+    text_area::TextArea,
+    wrap::wrap,
+};
 
-use log::debug;
+/// How many lines of a multi-line draft the input box shows at once
+static MAX_INPUT_LINES: usize = 8;
+/// Width of the login screen, including its border
+static FORM_WIDTH: u16 = 72;
+/// Columns reserved for the names of the fields on the login screen
+static LABEL_WIDTH: usize = 10;
 
 type Terminal = RatatuiTerminal<CrosstermBackend<Stdout>>;
 pub struct TUI {
     pub terminal: Terminal,
+    /// Message area of the last rendered frame
+    pub viewport: Viewport,
+    /// How far the text in the input box is scrolled
+    input_scroll: InputScroll,
+}
+
+/// Scroll offsets of the input box, in lines and display columns
+#[derive(Default)]
+struct InputScroll {
+    row: usize,
+    column: usize,
+}
+
+/// Size of the message area, in lines and columns available for text
+#[derive(Default, Clone, Copy)]
+pub struct Viewport {
+    pub width: u16,
+    pub height: u16,
+    /// How many lines the active group can be scrolled up
+    pub max_scroll: usize,
 }
 
 pub enum TUIMessage {
@@ -29,11 +69,24 @@ impl TUI {
     pub fn new() -> Self {
         let terminal =
             Terminal::new(CrosstermBackend::new(stdout())).expect("Failed to connect to terminal");
-        Self { terminal }
+        Self {
+            terminal,
+            viewport: Viewport::default(),
+            input_scroll: InputScroll::default(),
+        }
     }
 
     pub async fn render(&mut self, model: &Model) -> anyhow::Result<()> {
-        self.terminal.draw(|frame| TUI::draw(frame, model))?;
+        let mut viewport = self.viewport;
+        let input_scroll = &mut self.input_scroll;
+        self.terminal
+            .draw(|frame| viewport = TUI::draw(frame, model, input_scroll))?;
+        self.viewport = viewport;
+        Ok(())
+    }
+
+    pub fn render_form(&mut self, form: &Form) -> anyhow::Result<()> {
+        self.terminal.draw(|frame| TUI::draw_form(frame, form))?;
         Ok(())
     }
 
@@ -48,77 +101,492 @@ impl TUI {
             .unwrap();
     }
 
-    fn draw(frame: &mut Frame, model: &Model) {
-        let area = frame.size();
+    /// Number of lines a message takes up in the message area
+    pub fn line_count(
+        &self,
+        previous: Option<&ChatMessage>,
+        message: &ChatMessage,
+        model: &Model,
+    ) -> usize {
+        TUI::message_lines(previous, message, model, self.viewport.width as usize).len()
+    }
+
+    /// Draw the whole UI, returns the size of the message area
+    fn draw(frame: &mut Frame, model: &Model, input_scroll: &mut InputScroll) -> Viewport {
+        let outer = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![
+                Constraint::Length(1),
+                Constraint::Length(1),
+                Constraint::Min(0),
+            ])
+            .split(frame.size());
+
         frame.render_widget(
             Paragraph::new(format!(
-                "Chat Client, logged into \"{}\" in the group \"{}\" as \"{}\"",
-                model.url, model.group, model.username
+                "Chat Client, logged into \"{}\" as \"{}\"",
+                model.url, model.username
             )),
-            area,
+            outer[0],
         );
+        TUI::draw_status(frame, model, outer[0]);
+        TUI::draw_tabs(frame, model, outer[1]);
 
         let layout = Layout::default()
             .direction(Direction::Vertical)
-            .constraints(vec![Constraint::Percentage(90), Constraint::Min(3)])
-            .split(frame.size());
+            .constraints(vec![
+                Constraint::Min(0),
+                Constraint::Length(model.notices.len() as u16),
+                // Grow the input box with the lines of the draft
+                Constraint::Length(model.text_area.line_count().min(MAX_INPUT_LINES) as u16 + 2),
+            ])
+            .split(outer[2]);
 
-        let t = &model.text_area;
-        let avaliable_space = (layout[1].height - 2) * (layout[1].width - 2);
+        TUI::draw_notices(frame, model, layout[1]);
+        TUI::draw_input(frame, model, layout[2], input_scroll);
 
-        let text = if avaliable_space < t.len() as u16 {
-            debug!("Text doesnt fits");
-            format!("{}{}", &t[..avaliable_space as usize - 1], block::FULL)
-        } else {
-            t.to_string()
+        // Render all messages line by line alligned to bottom
+        let mut area = layout[0];
+        let mut viewport = Viewport {
+            width: area.width.saturating_sub(2),
+            height: area.height,
+            max_scroll: 0,
+        };
+        let Some(group) = model.active_group() else {
+            return viewport;
         };
 
-        let block = Block::default().borders(Borders::ALL).title("Input");
+        // Show where the view is while scrolled up or loading
+        if group.scroll > 0 {
+            let text = match group.new_below {
+                0 => "↓ More messages below (Ctrl+End to jump to the newest)".to_string(),
+                1 => "↓ 1 new message below (Ctrl+End to jump to the newest)".to_string(),
+                n => format!(
+                    "↓ {} new messages below (Ctrl+End to jump to the newest)",
+                    n
+                ),
+            };
+            TUI::draw_indicator(frame, text, area.bottom().saturating_sub(1), area);
+            area.height = area.height.saturating_sub(1);
+        }
+        if group.loading_older {
+            TUI::draw_indicator(frame, "Loading older messages…".to_string(), area.y, area);
+            area.y += 1;
+            area.height = area.height.saturating_sub(1);
+        }
 
+        let lines = TUI::group_lines(group, model, viewport.width as usize);
+        let height = area.height as usize;
+        viewport.max_scroll = lines.len().saturating_sub(height);
+
+        let end = lines.len() - group.scroll.min(viewport.max_scroll);
+        let visible = &lines[end.saturating_sub(height)..end];
         frame.render_widget(
-            Paragraph::new(format!("{text}_"))
-                .wrap(Wrap { trim: true })
-                .block(block),
-            layout[1],
+            Paragraph::new(visible.to_vec()),
+            Rect {
+                x: area.left() + 1,
+                y: area.bottom() - visible.len() as u16,
+                width: viewport.width,
+                height: visible.len() as u16,
+            },
         );
+        viewport
+    }
 
-        // Render all messages line by line alligned to bottom
-        let messages = model
-            .messages
-            .iter()
-            .rev()
-            .take((layout[0].height - 1) as usize);
-        let mut y = layout[0].bottom() - 1;
-        for message in messages {
-            let line = Line::from(vec![
-                if message.username == model.username {
-                    Span::styled(
-                        &message.username,
-                        ratatui::style::Style::default().bold().on_dark_gray(),
-                    )
-                } else {
-                    Span::styled(&message.username, ratatui::style::Style::default())
-                },
-                ": ".into(),
-                (&message.message).into(),
-            ]);
+    /// Draw the input box and place the cursor in it.
+    ///
+    /// Lines longer than the box scroll sideways together, the scroll offsets
+    /// only change when the cursor would leave the visible part, so moving the
+    /// cursor does not make the text jump around.
+    fn draw_input(frame: &mut Frame, model: &Model, area: Rect, scroll: &mut InputScroll) {
+        // While searching the history show the found message instead of the draft
+        let mut found = TextArea::default();
+        let (text_area, title) = match model.search {
+            Some(ref search) => {
+                let entry = search
+                    .found
+                    .and_then(|i| model.history.entries(&model.active_group()?.name).get(i));
+                if let Some(entry) = entry {
+                    found.set(entry.clone());
+                }
+                let title = match (entry, search.query.is_empty()) {
+                    (None, false) => format!("Search sent messages: {} (no match)", search.query),
+                    _ => format!("Search sent messages: {}", search.query),
+                };
+                (&found, title)
+            }
+            None => (&model.text_area, "Input".to_string()),
+        };
+
+        let width = area.width.saturating_sub(2) as usize;
+        let height = area.height.saturating_sub(2) as usize;
+        let text = text_area.text();
+        let (row, column) = text_area.cursor_position();
+
+        // One more column for the cursor behind the longest line
+        let longest = text.split('\n').map(|l| l.width()).max().unwrap_or(0);
+        follow(&mut scroll.column, column, width, longest + 1);
+        follow(&mut scroll.row, row, height, text_area.line_count());
+
+        let visible: Vec<Line> = text
+            .split('\n')
+            .skip(scroll.row)
+            .take(height)
+            .map(|line| Line::from(visible_part(line, scroll.column, width)))
+            .collect();
+
+        let block = Block::default().borders(Borders::ALL).title(title);
+        frame.render_widget(Paragraph::new(visible).block(block), area);
+        frame.set_cursor(
+            area.x + 1 + (column - scroll.column) as u16,
+            area.y + 1 + (row - scroll.row) as u16,
+        );
+    }
 
+    /// Draw the login screen in the middle of the terminal, with the error of
+    /// the last attempt and the recently used servers below the fields
+    fn draw_form(frame: &mut Frame, form: &Form) {
+        let size = frame.size();
+        let width = size.width.min(FORM_WIDTH);
+        // Border and one column of padding on both sides
+        let inner_width = width.saturating_sub(4) as usize;
+        let value_width = inner_width.saturating_sub(LABEL_WIDTH).max(1);
+
+        let mut lines: Vec<Line> = Vec::new();
+        let mut cursor = None;
+        for field in form.fields() {
+            let label = match field {
+                Field::Server => "Server",
+                Field::Name => "Name",
+                Field::Groups => "Groups",
+                Field::Method => "Account",
+                Field::Password => "Password",
+                Field::Repeat => "Repeat",
+            };
+            let label_style = match field == form.focus {
+                true => ratatui::style::Style::default().reversed(),
+                false => ratatui::style::Style::default().bold(),
+            };
+            let mut spans = vec![
+                Span::styled(label, label_style),
+                Span::raw(" ".repeat(LABEL_WIDTH.saturating_sub(label.width()))),
+            ];
+
+            match form.text_area(field) {
+                None => {
+                    let method = match form.method {
+                        AuthMethod::Guest => "Guest",
+                        AuthMethod::Login => "Log in",
+                        AuthMethod::Register => "Register",
+                    };
+                    spans.push(Span::raw(format!("< {} >", method)));
+                }
+                Some(text_area) => {
+                    let secret = matches!(field, Field::Password | Field::Repeat);
+                    let (text, column) = match secret {
+                        // Only show how many characters were typed
+                        true => (
+                            "•".repeat(text_area.text().chars().count()),
+                            text_area.text()[..text_area.cursor()].chars().count(),
+                        ),
+                        false => (text_area.text().to_string(), text_area.cursor_position().1),
+                    };
+                    let scroll = column.saturating_sub(value_width - 1);
+                    if field == form.focus {
+                        cursor = Some((column - scroll, lines.len()));
+                    }
+                    if text.is_empty() && field == Field::Password && form.token.is_some() {
+                        spans.push(Span::styled(
+                            "(logged in, leave empty to use the session)",
+                            ratatui::style::Style::default().dark_gray(),
+                        ));
+                    } else {
+                        spans.push(Span::raw(visible_part(&text, scroll, value_width)));
+                    }
+                }
+            }
+            lines.push(Line::from(spans));
+        }
+
+        if form.connecting {
+            lines.push(Line::default());
+            lines.push(Line::styled(
+                "Connecting… (any key to cancel)",
+                ratatui::style::Style::default().dark_gray(),
+            ));
+        } else if let Some(ref error) = form.error {
+            lines.push(Line::default());
+            lines.extend(wrap(
+                &[Span::styled(
+                    error.clone(),
+                    ratatui::style::Style::default().red(),
+                )],
+                inner_width,
+                0,
+            ));
+        }
+
+        let recent = form.recent.servers();
+        if !recent.is_empty() {
+            lines.push(Line::default());
+            lines.push(Line::styled(
+                "Recent servers",
+                ratatui::style::Style::default().bold(),
+            ));
+            for (i, server) in recent.iter().enumerate() {
+                let text = format!(
+                    "Alt+{} {} @ {} ({})",
+                    i + 1,
+                    server.name,
+                    server.server,
+                    server.groups.join(", ")
+                );
+                lines.push(Line::from(visible_part(&text, 0, inner_width)));
+            }
+        }
+
+        let height = (lines.len() as u16 + 2).min(size.height);
+        let area = Rect {
+            x: (size.width - width) / 2,
+            y: (size.height - height) / 2,
+            width,
+            height,
+        };
+        frame.render_widget(
+            Block::default()
+                .borders(Borders::ALL)
+                .title("Connect to a chat server"),
+            area,
+        );
+        let inner = Rect {
+            x: area.x + 2,
+            y: area.y + 1,
+            width: inner_width as u16,
+            height: height.saturating_sub(2),
+        };
+        frame.render_widget(Paragraph::new(lines), inner);
+
+        if area.bottom() < size.height {
             frame.render_widget(
-                Paragraph::new(line).wrap(Wrap { trim: true }),
+                Paragraph::new(
+                    "Enter connect · Tab next field · ←→ account · Alt+1-9 recent · Esc quit",
+                )
+                .alignment(Alignment::Center)
+                .style(ratatui::style::Style::default().dark_gray()),
                 Rect {
-                    x: layout[0].left() + 1,
-                    y,
-                    width: layout[0].width - 2,
+                    x: 0,
+                    y: area.bottom(),
+                    width: size.width,
                     height: 1,
                 },
             );
-            y -= 1;
         }
+        if let (Some((column, row)), false) = (cursor, form.connecting) {
+            frame.set_cursor(
+                inner.x + (LABEL_WIDTH + column) as u16,
+                inner.y + row as u16,
+            );
+        }
+    }
+
+    /// Show the state of the connection at the right end of the title line
+    fn draw_status(frame: &mut Frame, model: &Model, area: Rect) {
+        let (text, style) = match model.connection {
+            ConnectionState::Connected => (
+                match model.latency {
+                    Some(latency) => format!("● connected, {} ms", latency.as_millis()),
+                    None => "● connected".to_string(),
+                },
+                ratatui::style::Style::default().green(),
+            ),
+            ConnectionState::Reconnecting { at } => {
+                let seconds = at
+                    .saturating_duration_since(Instant::now())
+                    .as_secs_f64()
+                    .ceil();
+                (
+                    format!("● reconnecting in {}s", seconds),
+                    ratatui::style::Style::default().yellow(),
+                )
+            }
+            ConnectionState::Connecting => (
+                "● reconnecting…".to_string(),
+                ratatui::style::Style::default().yellow(),
+            ),
+            ConnectionState::Offline => (
+                "● offline".to_string(),
+                ratatui::style::Style::default().red(),
+            ),
+        };
+        let width = (text.width() as u16 + 1).min(area.width);
+        frame.render_widget(
+            Paragraph::new(format!(" {}", text)).style(style),
+            Rect {
+                x: area.right() - width,
+                width,
+                ..area
+            },
+        );
+    }
+
+    /// Draw the notices above the input box, errors in red
+    fn draw_notices(frame: &mut Frame, model: &Model, area: Rect) {
+        let lines: Vec<Line> = model
+            .notices
+            .iter()
+            .map(|notice| {
+                let style = if notice.error {
+                    ratatui::style::Style::default().red()
+                } else {
+                    ratatui::style::Style::default().dark_gray()
+                };
+                Line::styled(notice.text.as_str(), style)
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(lines), area);
+    }
+
+    /// Draw a highlighted single line hint over the message area
+    fn draw_indicator(frame: &mut Frame, text: String, y: u16, area: Rect) {
+        frame.render_widget(
+            Paragraph::new(text)
+                .alignment(Alignment::Center)
+                .style(ratatui::style::Style::default().reversed()),
+            Rect {
+                x: area.x,
+                y,
+                width: area.width,
+                height: 1,
+            },
+        );
+    }
+
+    /// All lines of a group, oldest first, followed by the messages the
+    /// server did not confirm yet
+    fn group_lines(group: &Group, model: &Model, width: usize) -> Vec<Line<'static>> {
+        let mut lines = Vec::with_capacity(group.messages.len());
+        let mut previous = None;
+        for message in &group.messages {
+            lines.extend(TUI::message_lines(previous, message, model, width));
+            previous = Some(message);
+        }
+        for pending in model.outbox.pending(&model.url, &group.name) {
+            lines.extend(TUI::pending_lines(pending, model, width));
+        }
+        lines
+    }
+
+    /// The wrapped lines of a message waiting in the outbox, greyed out
+    fn pending_lines(pending: &Pending, model: &Model, width: usize) -> Vec<Line<'static>> {
+        let style = ratatui::style::Style::default().dark_gray();
+        let state = match pending.state {
+            State::Queued => Span::styled("pending ", style.italic()),
+            State::Sending => Span::styled("sending ", style.italic()),
+            State::Failed(_) => Span::styled("failed ", ratatui::style::Style::default().red()),
+        };
+        let mut spans = vec![state];
+        if pending.action {
+            spans.push(Span::styled("* ", style));
+        }
+        spans.push(Span::styled(model.username.as_str(), style.bold()));
+        spans.push(Span::styled(if pending.action { " " } else { ": " }, style));
+        spans.push(Span::styled(pending.message.as_str(), style));
+
+        let indent = spans[..spans.len() - 1].iter().map(Span::width).sum();
+        wrap(&spans, width, indent)
+    }
+
+    /// The wrapped lines of a message, preceded by a line with the date if it
+    /// is the first message of a day
+    fn message_lines(
+        previous: Option<&ChatMessage>,
+        message: &ChatMessage,
+        model: &Model,
+        width: usize,
+    ) -> Vec<Line<'static>> {
+        let mut lines = Vec::new();
+        if let Some(date) = local_date(message) {
+            if previous.and_then(local_date) != Some(date) {
+                lines.push(TUI::date_line(date, model));
+            }
+        }
+
+        // Indent wrapped lines to the start of the message text
+        let spans = TUI::message_spans(message, model);
+        let indent = spans[..spans.len() - 1].iter().map(Span::width).sum();
+        lines.extend(wrap(&spans, width, indent));
+        lines
+    }
+
+    fn message_spans<'a>(message: &'a ChatMessage, model: &Model) -> Vec<Span<'a>> {
+        let mut spans = Vec::with_capacity(4);
+        if let Some(timestamp) = message.timestamp {
+            let time = format_time(timestamp, &model.time_format);
+            spans.push(Span::styled(
+                format!("{} ", time),
+                ratatui::style::Style::default().dark_gray(),
+            ));
+        }
+        // Own messages sent from the outbox reached the server
+        if message.key.is_some() && message.username == model.username {
+            spans.push(Span::styled("✓ ", ratatui::style::Style::default().green()));
+        }
+        if message.action {
+            spans.push("* ".into());
+        }
+        spans.push(if message.username == model.username {
+            Span::styled(
+                &message.username,
+                ratatui::style::Style::default().bold().on_dark_gray(),
+            )
+        } else {
+            Span::styled(&message.username, ratatui::style::Style::default())
+        });
+        spans.push(if message.action { " " } else { ": " }.into());
+        spans.push((&message.message).into());
+        spans
+    }
+
+    fn date_line(date: NaiveDate, model: &Model) -> Line<'static> {
+        Line::styled(
+            format!("── {} ──", format_date(date, &model.date_format)),
+            ratatui::style::Style::default().dark_gray(),
+        )
+    }
+
+    /// Render one tab per joined group, with the number of unread messages
+    fn draw_tabs(frame: &mut Frame, model: &Model, area: Rect) {
+        let titles: Vec<Line> = model
+            .groups
+            .iter()
+            .map(|group| {
+                if group.unread > 0 {
+                    Line::from(vec![
+                        Span::raw(&group.name),
+                        Span::styled(
+                            format!(" ({})", group.unread),
+                            ratatui::style::Style::default().bold(),
+                        ),
+                    ])
+                } else {
+                    Line::from(group.name.as_str())
+                }
+            })
+            .collect();
+
+        frame.render_widget(
+            Tabs::new(titles)
+                .select(model.active)
+                .highlight_style(ratatui::style::Style::default().reversed()),
+            area,
+        );
     }
 
     /// Enter raw mode and the alternate screen
     pub fn enter(&mut self) -> Result<()> {
         stdout().execute(EnterAlternateScreen)?;
+        stdout().execute(EnableMouseCapture)?;
+        stdout().execute(EnableBracketedPaste)?;
         enable_raw_mode()?;
         self.terminal.clear()?;
         self.terminal.show_cursor()?;
@@ -127,6 +595,8 @@ impl TUI {
 
     /// Exit raw mode and the alternate screen
     pub fn exit() -> Result<()> {
+        stdout().execute(DisableBracketedPaste)?;
+        stdout().execute(DisableMouseCapture)?;
         stdout().execute(LeaveAlternateScreen)?;
         disable_raw_mode()?;
         Ok(())
@@ -137,14 +607,81 @@ impl TUI {
     pub fn initialize_panic_handler() {
         let original_hook = std::panic::take_hook();
         std::panic::set_hook(Box::new(move |panic_info| {
-            crossterm::execute!(std::io::stderr(), crossterm::terminal::LeaveAlternateScreen)
-                .unwrap();
+            crossterm::execute!(
+                std::io::stderr(),
+                crossterm::event::DisableBracketedPaste,
+                crossterm::event::DisableMouseCapture,
+                crossterm::terminal::LeaveAlternateScreen
+            )
+            .unwrap();
             crossterm::terminal::disable_raw_mode().unwrap();
             original_hook(panic_info);
         }));
     }
 }
 
+/// Keep `position` visible in a window of `size` cells starting at `scroll`,
+/// without scrolling past `total`
+fn follow(scroll: &mut usize, position: usize, size: usize, total: usize) {
+    let size = size.max(1);
+    if position < *scroll {
+        *scroll = position;
+    } else if position >= *scroll + size {
+        *scroll = position + 1 - size;
+    }
+    // Do not leave empty space after deleting text
+    *scroll = (*scroll).min(total.saturating_sub(size));
+}
+
+/// The part of a line that is visible when scrolled by `scroll` columns
+fn visible_part(line: &str, scroll: usize, width: usize) -> String {
+    let mut visible = String::new();
+    let mut column = 0;
+    for grapheme in line.graphemes(true) {
+        let start = column;
+        column += grapheme.width();
+        if start < scroll {
+            // A wide grapheme cut off at the left edge is shown as blank
+            if column > scroll {
+                visible.push_str(&" ".repeat(column - scroll));
+            }
+            continue;
+        }
+        if column > scroll + width {
+            break;
+        }
+        visible.push_str(grapheme);
+    }
+    visible
+}
+
+/// The day a message was sent on, in the local timezone
+fn local_date(message: &ChatMessage) -> Option<NaiveDate> {
+    message
+        .timestamp
+        .map(|t| t.with_timezone(&Local).date_naive())
+}
+
+/// Format a timestamp in the local timezone, falling back to the default
+/// format if the configured one is invalid
+fn format_time(timestamp: DateTime<Utc>, format: &str) -> String {
+    let local = timestamp.with_timezone(&Local);
+    let mut formatted = String::new();
+    if write!(formatted, "{}", local.format(format)).is_err() {
+        formatted = local.format(DEFAULT_TIME_FORMAT).to_string();
+    }
+    formatted
+}
+
+/// Format a date, falling back to the default format if the configured one is invalid
+fn format_date(date: NaiveDate, format: &str) -> String {
+    let mut formatted = String::new();
+    if write!(formatted, "{}", date.format(format)).is_err() {
+        formatted = date.format(DEFAULT_DATE_FORMAT).to_string();
+    }
+    formatted
+}
+
 impl Default for TUI {
     fn default() -> Self {
         Self::new()
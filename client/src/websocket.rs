@@ -1,51 +1,151 @@
-use anyhow::Result;
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use chat_protocol::{ClientFrame, ServerFrame};
 use futures_util::{SinkExt, StreamExt};
 use log::{debug, error, info};
+use sha2::{Digest, Sha256};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    tungstenite::{http::Uri, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// How to check the certificate of a wss:// server, by default it has to be
+/// signed by a CA the system trusts
+#[derive(Debug, Clone, Default)]
+pub struct TlsOptions {
+    /// PEM certificate of an additional CA to trust
+    pub ca: Option<PathBuf>,
+    /// SHA-256 fingerprint of the server certificate, in hex with optional
+    /// colons. If set, the server must present exactly this certificate and
+    /// the CA is not checked, for self-signed certificates.
+    pub pin: Option<String>,
+}
+
+impl TlsOptions {
+    fn is_default(&self) -> bool {
+        self.ca.is_none() && self.pin.is_none()
+    }
+}
+
+/// How often the server is pinged and how long it may stay silent before the
+/// connection is given up
+#[derive(Debug, Clone, Copy)]
+pub struct Heartbeat {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
 
 pub struct Websocket {
-    pub read: tokio::sync::mpsc::UnboundedReceiver<String>,
-    pub write: tokio::sync::mpsc::UnboundedSender<String>,
+    pub read: tokio::sync::mpsc::UnboundedReceiver<ServerFrame>,
+    pub write: tokio::sync::mpsc::UnboundedSender<ClientFrame>,
+    /// Round trip time of the last answered ping
+    pub latency: tokio::sync::watch::Receiver<Option<Duration>>,
 }
 
 impl Websocket {
-    pub fn loopback() -> Self {
-        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    /// A websocket that is not connected to anything
+    pub fn disconnected() -> Self {
+        let (_, rx) = tokio::sync::mpsc::unbounded_channel();
+        let (tx, _) = tokio::sync::mpsc::unbounded_channel();
+        let (_, latency) = tokio::sync::watch::channel(None);
         Self {
             read: rx,
             write: tx,
+            latency,
         }
     }
 
-    pub async fn connect(url: &str) -> Result<Self> {
-        let (ws_stream, _) = tokio_tungstenite::connect_async(url).await?;
+    /// Connect to a server. It is pinged regularly to measure the latency,
+    /// and the connection is closed if it did not answer for the timeout of
+    /// the heartbeat.
+    pub async fn connect(url: &str, tls: &TlsOptions, heartbeat: Heartbeat) -> Result<Self> {
+        let ws_stream = if url.starts_with("wss://") && !tls.is_default() {
+            Self::connect_tls(url, tls).await?
+        } else {
+            tokio_tungstenite::connect_async(url).await?.0
+        };
 
         info!("WebSocket handshake has been successfully completed");
 
         let (tx_read, rx_read) = tokio::sync::mpsc::unbounded_channel();
         let (tx_write, mut rx_write) = tokio::sync::mpsc::unbounded_channel();
+        let (tx_latency, rx_latency) = tokio::sync::watch::channel(None);
 
         tokio::spawn(async move {
             let (mut ws_write, mut ws_read) = ws_stream.split();
+            let mut ping = tokio::time::interval(heartbeat.interval);
+            // When the unanswered ping was sent
+            let mut ping_sent = None;
+            let mut last_seen = Instant::now();
 
             loop {
+                let deadline = last_seen + heartbeat.timeout;
                 tokio::select! {
-                    Some(msg) = rx_write.recv() => {
+                    frame = rx_write.recv() => {
+                        // The application dropped the connection
+                        let Some(frame) = frame else {
+                            break;
+                        };
+                        let msg = chat_protocol::serialize(&frame);
                         debug!("Sending message: {}", msg);
-                        ws_write.send(tokio_tungstenite::tungstenite::Message::Text(msg)).await.unwrap();
+                        if let Err(e) = ws_write.send(Message::Text(msg)).await {
+                            error!("Error writing to websocket: {}", e);
+                            break;
+                        }
                     }
-                    Some(msg) = ws_read.next() => {
-                        match msg {
-                            Ok(msg) => {
-                                let msg = msg.into_text().expect("Failed to convert message to text");
-                                debug!("Recieved message: {}", msg);
-                                tx_read.send(msg).unwrap();
-                            }
-                            Err(e) => {
+                    msg = ws_read.next() => {
+                        let msg = match msg {
+                            Some(Ok(msg)) => msg,
+                            // Closed by the server
+                            None => break,
+                            Some(Err(e)) => {
                                 error!("Error reading from websocket: {}", e);
                                 break;
                             }
+                        };
+                        last_seen = Instant::now();
+                        let msg = match msg {
+                            Message::Text(msg) => msg,
+                            Message::Pong(_) => {
+                                if let Some(sent) = ping_sent.take() {
+                                    tx_latency.send_replace(Some(last_seen - sent));
+                                }
+                                continue;
+                            }
+                            Message::Close(_) => break,
+                            // Pings are answered by tungstenite
+                            _ => continue,
+                        };
+                        debug!("Recieved message: {}", msg);
+                        match chat_protocol::deserialize(&msg) {
+                            Ok(frame) => {
+                                // The application dropped the connection
+                                if tx_read.send(frame).is_err() {
+                                    break;
+                                }
+                            }
+                            Err(e) => error!("Failed to deserialize message: {:#}", e),
                         }
                     }
+                    _ = ping.tick() => {
+                        // Measure from the oldest unanswered ping
+                        ping_sent.get_or_insert_with(Instant::now);
+                        if let Err(e) = ws_write.send(Message::Ping(Vec::new())).await {
+                            error!("Error sending ping: {}", e);
+                            break;
+                        }
+                    }
+                    _ = tokio::time::sleep_until(deadline.into()) => {
+                        error!("The server did not answer for {:?}", heartbeat.timeout);
+                        break;
+                    }
                 }
             }
         });
@@ -53,15 +153,65 @@ impl Websocket {
         Ok(Self {
             read: rx_read,
             write: tx_write,
+            latency: rx_latency,
         })
     }
 
-    pub fn send(&mut self, msg: String) -> anyhow::Result<()> {
-        self.write.send(msg).unwrap();
-        Ok(())
+    /// Connect to a wss:// server, checking its certificate against the
+    /// configured CA or pin before the websocket handshake
+    async fn connect_tls(url: &str, options: &TlsOptions) -> Result<WebSocket> {
+        let uri: Uri = url.parse()?;
+        let host = uri
+            .host()
+            .ok_or_else(|| anyhow::anyhow!("No host in {}", url))?;
+        let port = uri.port_u16().unwrap_or(443);
+
+        let mut builder = native_tls::TlsConnector::builder();
+        if let Some(ref ca) = options.ca {
+            let pem = std::fs::read(ca)
+                .with_context(|| format!("Failed to read CA certificate {}", ca.display()))?;
+            builder.add_root_certificate(native_tls::Certificate::from_pem(&pem)?);
+        }
+        if options.pin.is_some() {
+            // The pin replaces the usual checks
+            builder.danger_accept_invalid_certs(true);
+            builder.danger_accept_invalid_hostnames(true);
+        }
+        let connector = tokio_native_tls::TlsConnector::from(builder.build()?);
+
+        let tcp = TcpStream::connect((host, port)).await?;
+        let stream = connector.connect(host, tcp).await?;
+
+        if let Some(ref pin) = options.pin {
+            let certificate = stream
+                .get_ref()
+                .peer_certificate()?
+                .ok_or_else(|| anyhow::anyhow!("The server sent no certificate"))?;
+            let fingerprint: String = Sha256::digest(certificate.to_der()?)
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            let pin = pin.replace(':', "").to_lowercase();
+            if fingerprint != pin {
+                anyhow::bail!(
+                    "The certificate of the server does not match the pin, its fingerprint is {}",
+                    fingerprint
+                );
+            }
+        }
+
+        let (ws_stream, _) =
+            tokio_tungstenite::client_async(url, MaybeTlsStream::NativeTls(stream)).await?;
+        Ok(ws_stream)
+    }
+
+    pub fn send(&mut self, frame: ClientFrame) -> anyhow::Result<()> {
+        self.write
+            .send(frame)
+            .map_err(|_| anyhow::anyhow!("Not connected to the server"))
     }
 
-    pub async fn recieve(&mut self) -> anyhow::Result<String> {
+    pub async fn recieve(&mut self) -> anyhow::Result<ServerFrame> {
         match self.read.recv().await {
             Some(msg) => Ok(msg),
             None => Err(anyhow::anyhow!("Failed to recieve message")),
@@ -0,0 +1,162 @@
+use ratatui::{
+    style::Style,
+    text::{Line, Span},
+};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Wrap styled text into lines at most `width` columns wide.
+///
+/// Lines are broken after whitespace where possible, words longer than a line
+/// are split between graphemes. Newlines in the text always start a new line.
+/// Continuation lines are indented by `indent` columns, unless that would take
+/// up more than half of the width.
+pub fn wrap(spans: &[Span], width: usize, indent: usize) -> Vec<Line<'static>> {
+    let width = width.max(1);
+    let indent = if indent * 2 <= width { indent } else { 0 };
+
+    let mut lines = Vec::new();
+    let mut current = Row::new(0);
+
+    for span in spans {
+        for grapheme in span.content.graphemes(true) {
+            if grapheme == "\n" || grapheme == "\r\n" {
+                lines.push(std::mem::replace(&mut current, Row::new(indent)).into_line());
+                continue;
+            }
+
+            let is_whitespace = grapheme.chars().all(char::is_whitespace);
+            let grapheme_width = grapheme.width();
+            while current.width + grapheme_width > width && !current.cells.is_empty() {
+                // Move the last word to the next line if it fits there,
+                // otherwise split it where the line is full
+                let rest = match current.last_break.take() {
+                    Some(index) if current.width_from(index) + grapheme_width <= width - indent => {
+                        current.cells.split_off(index)
+                    }
+                    _ => Vec::new(),
+                };
+                lines.push(std::mem::replace(&mut current, Row::new(indent)).into_line());
+
+                current.wrapped = true;
+                for (grapheme, style) in rest {
+                    current.push(grapheme, style, false);
+                }
+            }
+
+            // Do not start a wrapped line with the whitespace it was broken at
+            if is_whitespace && current.is_wrapped_start() {
+                continue;
+            }
+
+            current.push(grapheme.to_string(), span.style, is_whitespace);
+        }
+    }
+    lines.push(current.into_line());
+    lines
+}
+
+/// A line that is being filled with graphemes
+struct Row {
+    indent: usize,
+    cells: Vec<(String, Style)>,
+    /// Display width including the indent
+    width: usize,
+    /// Index in `cells` after the last whitespace, where the line can be broken
+    last_break: Option<usize>,
+    /// Whether this line continues a line that was too long
+    wrapped: bool,
+}
+
+impl Row {
+    fn new(indent: usize) -> Self {
+        Self {
+            indent,
+            cells: Vec::new(),
+            width: indent,
+            last_break: None,
+            wrapped: false,
+        }
+    }
+
+    /// Display width of the cells starting at `index`
+    fn width_from(&self, index: usize) -> usize {
+        self.cells[index..].iter().map(|(g, _)| g.width()).sum()
+    }
+
+    fn is_wrapped_start(&self) -> bool {
+        self.wrapped && self.cells.is_empty()
+    }
+
+    fn push(&mut self, grapheme: String, style: Style, is_whitespace: bool) {
+        self.width += grapheme.width();
+        self.cells.push((grapheme, style));
+        if is_whitespace {
+            self.last_break = Some(self.cells.len());
+        }
+    }
+
+    /// Merge neighbouring graphemes with the same style into spans
+    fn into_line(self) -> Line<'static> {
+        let mut spans: Vec<Span<'static>> = Vec::new();
+        for (grapheme, style) in self.cells {
+            match spans.last_mut() {
+                Some(last) if last.style == style => last.content.to_mut().push_str(&grapheme),
+                _ => spans.push(Span::styled(grapheme, style)),
+            }
+        }
+        if self.indent > 0 {
+            spans.insert(0, Span::raw(" ".repeat(self.indent)));
+        }
+        Line::from(spans)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrapped(text: &str, width: usize, indent: usize) -> Vec<String> {
+        wrap(&[Span::raw(text)], width, indent)
+            .iter()
+            .map(|line| line.spans.iter().map(|s| s.content.as_ref()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn breaks_after_whitespace() {
+        assert_eq!(wrapped("hello big world", 10, 0), ["hello big ", "world"]);
+    }
+
+    #[test]
+    fn splits_words_longer_than_a_line() {
+        assert_eq!(wrapped("abcdefgh", 3, 0), ["abc", "def", "gh"]);
+    }
+
+    #[test]
+    fn indents_continuation_lines() {
+        assert_eq!(
+            wrapped("aaaa bbbb cccc", 8, 2),
+            ["aaaa ", "  bbbb ", "  cccc"]
+        );
+        // An indent of more than half the width is dropped
+        assert_eq!(wrapped("aaaa bbbb", 6, 4), ["aaaa ", "bbbb"]);
+    }
+
+    #[test]
+    fn newlines_start_a_new_line() {
+        assert_eq!(
+            wrapped("one\ntwo\r\n\nthree", 20, 1),
+            ["one", " two", " ", " three"]
+        );
+    }
+
+    #[test]
+    fn wide_graphemes_are_not_split() {
+        // Every character takes two columns, the third does not fit
+        assert_eq!(wrapped("日本語", 5, 0), ["日本", "語"]);
+        // A family emoji is one grapheme of several characters
+        let family = "👨\u{200d}👩\u{200d}👧";
+        assert_eq!(wrapped(&format!("a{}", family), 2, 0), ["a", family]);
+    }
+}
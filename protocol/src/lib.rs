@@ -0,0 +1,344 @@
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Version of the wire format. Increase this when frames change in a way
+/// that older peers can not understand.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    /// Id of the message on the server, assigned when it is stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<i64>,
+    pub username: String,
+    pub message: String,
+    /// Whether the message describes an action of the user, sent with `/me`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub action: bool,
+    /// When the server recieved the message. Assigned by the server, messages
+    /// stored before timestamps were recorded have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<DateTime<Utc>>,
+    /// Idempotency key the sender chose, see [`ClientFrame::Chat`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+/// Frames sent from the client to the server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientFrame {
+    /// First frame of every connection, the server answers with
+    /// [`ServerFrame::Accepted`] or [`ServerFrame::Rejected`] and then
+    /// joins the given groups
+    Hello {
+        username: String,
+        groups: Vec<String>,
+        /// How the client proves it may use the name, guests send none
+        #[serde(default)]
+        auth: Auth,
+    },
+    /// Join another group, the server answers with [`ServerFrame::Joined`]
+    /// or [`ServerFrame::JoinFailed`]
+    Join {
+        group: String,
+        /// Id of the newest message the client has, to only get the
+        /// messages missed after reconnecting
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        since: Option<i64>,
+    },
+    /// Leave a group, the server answers with [`ServerFrame::Parted`]
+    Part { group: String },
+    /// Send a message to a joined group. The server adds the name of the
+    /// sender and the timestamp and answers with [`ServerFrame::Delivered`]
+    /// or [`ServerFrame::Undelivered`].
+    Chat {
+        group: String,
+        message: String,
+        /// Whether the message describes an action, sent with `/me`
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        action: bool,
+        /// Chosen by the client to send the message again after reconnecting.
+        /// The server stores a message with the same key in a group only once
+        /// and sends it back with the key, so the client knows it arrived.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key: Option<String>,
+    },
+    /// Request messages older than the message with id `before`, the server
+    /// answers with [`ServerFrame::Older`]
+    LoadOlder { group: String, before: i64 },
+    /// Change the name shown to others, the server answers with
+    /// [`ServerFrame::NickChanged`] or [`ServerFrame::NickFailed`]
+    Nick { nick: String },
+}
+
+/// Credentials sent with the hello
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum Auth {
+    /// Chat as a guest, only names without an account can be used
+    #[default]
+    Guest,
+    /// Create an account for the name
+    Register { password: String },
+    /// Log into an existing account
+    Password { password: String },
+    /// Log in again with the token from a previous [`ServerFrame::Accepted`],
+    /// only under the name of the account
+    Token { token: String },
+}
+
+/// Frames sent from the server to the client
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerFrame {
+    /// The hello was accepted. Clients that logged into an account get a
+    /// token to log in again after losing the connection.
+    Accepted {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
+    },
+    /// The hello was rejected, the server closes the connection afterwards
+    Rejected { reason: RejectReason },
+    /// The client is now a member of the group, its history follows
+    Joined { group: String },
+    /// The group could not be joined
+    JoinFailed { group: String, reason: RejectReason },
+    /// The client is no longer a member of the group
+    Parted { group: String },
+    /// A new message in a joined group
+    Chat { group: String, message: ChatMessage },
+    /// The message sent with [`ClientFrame::Chat`] was stored with the given id
+    Delivered {
+        group: String,
+        /// Key the client sent the message with
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key: Option<String>,
+        id: i64,
+    },
+    /// The message sent with [`ClientFrame::Chat`] was refused or could not
+    /// be stored
+    Undelivered {
+        group: String,
+        /// Key the client sent the message with
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key: Option<String>,
+        reason: RejectReason,
+    },
+    /// Messages sent after joining a group, oldest first
+    History {
+        group: String,
+        messages: Vec<ChatMessage>,
+        /// Whether there are older messages that can be loaded
+        has_more: bool,
+        /// Set if the messages are exactly the ones after this id, as asked
+        /// for when joining, `has_more` is meaningless then. Otherwise they
+        /// are the newest messages of the group and replace what the client has.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        since: Option<i64>,
+    },
+    /// Messages requested with [`ClientFrame::LoadOlder`], oldest first
+    Older {
+        group: String,
+        messages: Vec<ChatMessage>,
+        /// Whether there are even older messages
+        has_more: bool,
+    },
+    /// A client changed its name, sent to the client itself and everyone
+    /// sharing a group with it
+    NickChanged { old: String, new: String },
+    /// The name could not be changed
+    NickFailed { nick: String, reason: RejectReason },
+    /// Something went wrong on the server
+    Error { reason: String },
+}
+
+/// Why the server refused a hello, a login, a join, a name change or a message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RejectReason {
+    /// The client speaks a different protocol version
+    UnsupportedVersion {
+        supported: u32,
+    },
+    /// The first frame was not a hello
+    ExpectedHello,
+    /// The client is already a member of the group
+    AlreadyJoined {
+        group: String,
+    },
+    InvalidUsername {
+        problem: String,
+    },
+    InvalidGroup {
+        problem: String,
+    },
+    /// The name is reserved for the server
+    ReservedName {
+        name: String,
+    },
+    /// Another client uses the name
+    NickTaken {
+        name: String,
+    },
+    /// The name belongs to an account, log in to use it
+    RegisteredName {
+        name: String,
+    },
+    /// There already is an account with the name
+    AccountExists {
+        name: String,
+    },
+    /// The account does not exist or the password is wrong
+    WrongPassword,
+    /// The token is unknown, expired or belongs to another account
+    InvalidToken,
+    InvalidPassword {
+        problem: String,
+    },
+    /// Messages can only be sent to joined groups
+    NotMember {
+        group: String,
+    },
+    EmptyMessage,
+    MessageTooLong {
+        /// Longest allowed message, in characters
+        max: usize,
+    },
+    /// The client sent too many messages in a short time
+    RateLimited,
+    /// The server failed to store the message
+    NotStored,
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::UnsupportedVersion { supported } => {
+                write!(f, "the server only supports protocol version {}", supported)
+            }
+            RejectReason::ExpectedHello => write!(f, "the client did not introduce itself"),
+            RejectReason::AlreadyJoined { group } => {
+                write!(f, "already a member of '{}'", group)
+            }
+            RejectReason::InvalidUsername { problem } => write!(f, "invalid username: {}", problem),
+            RejectReason::InvalidGroup { problem } => write!(f, "invalid group: {}", problem),
+            RejectReason::ReservedName { name } => write!(f, "the name '{}' is reserved", name),
+            RejectReason::NickTaken { name } => write!(f, "the name '{}' is already in use", name),
+            RejectReason::RegisteredName { name } => {
+                write!(
+                    f,
+                    "the name '{}' belongs to an account, log in to use it",
+                    name
+                )
+            }
+            RejectReason::AccountExists { name } => {
+                write!(f, "there already is an account named '{}'", name)
+            }
+            RejectReason::WrongPassword => write!(f, "wrong name or password"),
+            RejectReason::InvalidToken => write!(f, "the login expired, log in again"),
+            RejectReason::InvalidPassword { problem } => write!(f, "invalid password: {}", problem),
+            RejectReason::NotMember { group } => write!(f, "not a member of '{}'", group),
+            RejectReason::EmptyMessage => write!(f, "the message is empty"),
+            RejectReason::MessageTooLong { max } => {
+                write!(f, "the message is longer than {} characters", max)
+            }
+            RejectReason::RateLimited => write!(f, "too many messages, wait a moment"),
+            RejectReason::NotStored => write!(f, "the server could not store the message"),
+        }
+    }
+}
+
+/// Every frame on the wire is wrapped in an envelope carrying the protocol version
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope<T> {
+    version: u32,
+    #[serde(flatten)]
+    frame: T,
+}
+
+/// Only the version of an envelope, used to reject frames before parsing them
+#[derive(Deserialize)]
+struct Version {
+    version: u32,
+}
+
+#[derive(Debug)]
+pub enum FrameError {
+    /// The frame was sent by a peer using another protocol version
+    UnsupportedVersion(u32),
+    /// The frame is not valid JSON or does not match any known frame
+    Malformed(serde_json::Error),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::UnsupportedVersion(version) => write!(
+                f,
+                "Unsupported protocol version {} (expected {})",
+                version, PROTOCOL_VERSION
+            ),
+            FrameError::Malformed(e) => write!(f, "Malformed frame: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// Serialize a frame to a JSON string
+pub fn serialize<T: Serialize>(frame: &T) -> String {
+    serde_json::to_string(&Envelope {
+        version: PROTOCOL_VERSION,
+        frame,
+    })
+    .expect("Frames are always serializable")
+}
+
+/// Deserialize a frame from a JSON string, rejecting incompatible versions
+pub fn deserialize<T: DeserializeOwned>(s: &str) -> Result<T, FrameError> {
+    let Version { version } = serde_json::from_str(s).map_err(FrameError::Malformed)?;
+    if version != PROTOCOL_VERSION {
+        return Err(FrameError::UnsupportedVersion(version));
+    }
+
+    let envelope: Envelope<T> = serde_json::from_str(s).map_err(FrameError::Malformed)?;
+    Ok(envelope.frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_survive_a_round_trip() {
+        let join = r#"{"version":1,"type":"join","group":"lobby"}"#;
+        let frame: ClientFrame = deserialize(join).unwrap();
+        assert_eq!(serialize(&frame), join);
+
+        let error = ServerFrame::Error {
+            reason: "database is locked".to_string(),
+        };
+        assert_eq!(
+            deserialize::<ServerFrame>(&serialize(&error)).unwrap(),
+            error
+        );
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let frame = r#"{"version":2,"type":"join","group":"lobby"}"#;
+        assert_eq!(
+            deserialize::<ClientFrame>(frame).unwrap_err().to_string(),
+            "Unsupported protocol version 2 (expected 1)"
+        );
+    }
+
+    #[test]
+    fn frames_without_version_are_rejected() {
+        let frame = r#"{"type":"join","group":"lobby"}"#;
+        assert!(deserialize::<ClientFrame>(frame).is_err());
+    }
+}
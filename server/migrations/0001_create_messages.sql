@@ -0,0 +1,9 @@
+-- Messages of all groups
+CREATE TABLE IF NOT EXISTS messages (
+    id INTEGER PRIMARY KEY,
+    group_name TEXT NOT NULL,
+    username TEXT NOT NULL,
+    message TEXT NOT NULL,
+    -- UTC time the server recieved the message, NULL for old messages
+    created_at TEXT
+);
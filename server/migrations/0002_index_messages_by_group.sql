@@ -0,0 +1,2 @@
+-- History is always fetched per group, newest first
+CREATE INDEX IF NOT EXISTS messages_group_id ON messages (group_name, id);
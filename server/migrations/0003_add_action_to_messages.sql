@@ -0,0 +1,2 @@
+-- Messages sent with /me
+ALTER TABLE messages ADD COLUMN action BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,16 @@
+-- Registered users, names are unique regardless of case
+CREATE TABLE accounts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    -- Argon2 hash in PHC string format
+    password_hash TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- Tokens to log in again without the password, only their SHA-256 is stored
+CREATE TABLE sessions (
+    token_hash TEXT PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES accounts (id) ON DELETE CASCADE,
+    created_at TEXT NOT NULL,
+    last_used_at TEXT NOT NULL
+);
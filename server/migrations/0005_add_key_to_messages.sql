@@ -0,0 +1,5 @@
+-- Idempotency key chosen by the sending client, NULL for messages sent without one
+ALTER TABLE messages ADD COLUMN client_key TEXT;
+
+-- A client sending a message again after reconnecting must not store it twice
+CREATE UNIQUE INDEX messages_group_key ON messages (group_name, client_key);
@@ -1,53 +1,105 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::{Arc, Mutex},
 };
 
+use anyhow::Context;
+use chat_protocol::ServerFrame;
 use futures_util::FutureExt;
-use log::{debug, error, info};
+use log::{debug, info};
+use tokio::net::TcpListener;
+use tokio_native_tls::TlsAcceptor;
+
+use crate::{
+    config::Config,
+    connection::{Connection, Heartbeat},
+    database,
+    nicks::Nicks,
+    rate_limit::RateLimit,
+    session::Session,
+    websocket,
+};
 
-use crate::{connection::Connection, database, websocket};
+pub type Sender = tokio::sync::mpsc::UnboundedSender<ServerFrame>;
 
-type Sender = tokio::sync::mpsc::UnboundedSender<std::string::String>;
+/// Members of every group, by connection id
+pub type Groups = Arc<Mutex<HashMap<String, HashMap<u64, Sender>>>>;
 
-static DATABASE_URL: &str = "sqlite://database.db";
+/// An adress to accept connections on, TLS is used if an acceptor is given
+#[derive(Clone)]
+pub struct Listener {
+    pub adress: String,
+    pub tls: Option<TlsAcceptor>,
+}
 
 pub struct Application {
-    pub adress: String,
-    pub connections: Arc<Mutex<HashMap<String, Vec<Sender>>>>,
+    pub listeners: Vec<Listener>,
+    pub database_url: String,
+    /// How many messages are sent at once from the database
+    pub history_size: u32,
+    /// How often clients are pinged and how long they may stay silent
+    pub heartbeat: Heartbeat,
+    pub connections: Groups,
+    pub nicks: Nicks,
     pub db: Option<sqlx::SqlitePool>,
 }
 
 impl Application {
-    pub fn new(adress: &str) -> Self {
-        Self {
-            adress: adress.to_string(),
+    /// Set up the application from the config, fails if the TLS certificate
+    /// cannot be loaded
+    pub fn new(config: &Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            listeners: config.listeners()?,
+            database_url: config.database_url(),
+            history_size: config.history_size,
+            heartbeat: config.heartbeat(),
             connections: Arc::new(Mutex::new(HashMap::new())),
+            nicks: Nicks::default(),
             db: None,
-        }
+        })
     }
 
-    pub async fn run(&mut self) {
+    pub async fn run(&mut self) -> anyhow::Result<()> {
         // Connect to DB
-        if self.db.is_none() {
-            match database::establish_connection(DATABASE_URL).await {
-                Ok(db) => {
-                    self.db = Some(db);
-                    info!("Connected to database at {}", DATABASE_URL);
-                }
-                Err(e) => {
-                    error!("Failed to connect to database: {}", e);
-                }
+        let db = match self.db {
+            Some(ref db) => db.clone(),
+            None => {
+                let db = database::establish_connection(&self.database_url).await?;
+                info!("Connected to database at {}", self.database_url);
+
+                database::migrate(&db).await?;
+                self.db = Some(db.clone());
+                db
             }
-
-            database::create_table(self.db.as_ref().unwrap()).await;
-        }
+        };
 
         let (connection_sender, mut connection_receiver) = tokio::sync::mpsc::unbounded_channel();
         //let (message_sender, message_receiver) = tokio::sync::mpsc::unbounded_channel();
 
-        let adress = self.adress.clone();
-        tokio::spawn(websocket::accept_connections(adress, connection_sender));
+        // Bind every adress before accepting on any, so the server does not
+        // run with only some of them
+        let mut bound = Vec::with_capacity(self.listeners.len());
+        for listener in &self.listeners {
+            let tcp = TcpListener::bind(&listener.adress)
+                .await
+                .with_context(|| format!("Failed to bind to adress {}", listener.adress))?;
+            match listener.tls {
+                Some(_) => info!("Listening on: {} (TLS)", listener.adress),
+                None => info!("Listening on: {}", listener.adress),
+            }
+            bound.push((tcp, listener.tls.clone()));
+        }
+
+        for (tcp, tls) in bound {
+            tokio::spawn(websocket::accept_connections(
+                tcp,
+                tls,
+                connection_sender.clone(),
+                self.nicks.clone(),
+                db.clone(),
+                self.heartbeat,
+            ));
+        }
 
         loop {
             tokio::select! {
@@ -62,82 +114,22 @@ impl Application {
 
             };
         }
+        Ok(())
     }
 
     fn on_connection(&mut self, connection: Connection) {
-        let mut read_channel = connection.receiver;
-        let connections = self.connections.clone();
-
-        let db_connection = self.db.as_ref().cloned();
-
-        tokio::spawn(async move {
-            // await for the group to be set
-            while connection.group.lock().unwrap().is_none() {
-                tokio::task::yield_now().await;
-            }
-            let group = connection.group.lock().unwrap().as_ref().unwrap().clone();
-
-            // Send all messages from the database
-            if let Some(ref db) = db_connection {
-                let messages = crate::database::get_messages(db, &group).await;
-                info!("Sending {} messages from group '{}'", messages.len(), group);
-
-                for message in messages {
-                    if let Err(e) = Connection::send(&connection.sender, message.serialize()) {
-                        error!("Error sending message: {}", e);
-                    }
-                }
-            }
-
-            // Add the connection to the list of connections
-            connections
-                .lock()
-                .unwrap()
-                .entry(group.clone())
-                .or_default()
-                .push(connection.sender);
-
-            // Listen for messages
-            loop {
-                let msg = read_channel.recv().await;
-                if let Some(msg) = msg {
-                    debug!("Recieved message: {}", msg);
-
-                    let parsed = match crate::ChatMessage::deserialize(&msg) {
-                        Some(msg) => {
-                            debug!("Message from {}: {}", msg.username, msg.message);
-                            msg
-                        }
-                        None => {
-                            error!("Failed to deserialize message: {}", msg);
-                            continue;
-                        }
-                    };
-
-                    // Save message to database
-                    if let Some(ref db) = db_connection {
-                        crate::database::insert_message(db, &group, &parsed).await;
-                    }
-
-                    // Send message to everyone in the group
-                    let mut connections = connections.lock().unwrap();
-                    if let Some(group) = connection.group.lock().unwrap().as_ref() {
-                        if let Some(connections) = connections.get_mut(group) {
-                            connections.retain(|c| {
-                                if let Err(e) = Connection::send(c, msg.clone()) {
-                                    error!("Error sending message: {}", e);
-                                    false
-                                } else {
-                                    true
-                                }
-                            });
-                        }
-                    }
-                } else {
-                    error!("Connection closed");
-                    break;
-                }
-            }
-        });
+        let session = Session {
+            id: connection.id,
+            username: connection.username.clone(),
+            account: connection.account,
+            sender: connection.sender.clone(),
+            joined: HashSet::new(),
+            groups: self.connections.clone(),
+            nicks: self.nicks.clone(),
+            db: self.db.clone(),
+            history_size: self.history_size,
+            rate_limit: RateLimit::default(),
+        };
+        tokio::spawn(session.run(connection));
     }
 }
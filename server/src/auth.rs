@@ -0,0 +1,169 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, rand_core::RngCore, SaltString},
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+};
+use chat_protocol::{Auth, RejectReason};
+use log::info;
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+
+use crate::{database, validation};
+
+/// How long a session token stays valid after it was last used
+static TOKEN_LIFETIME_DAYS: i64 = 30;
+
+/// Result of a successful login
+pub struct Login {
+    /// Account the client logged into, None for guests
+    pub account: Option<i64>,
+    /// Token to log in again, sent to the client
+    pub token: Option<String>,
+}
+
+/// Check the credentials of a hello and whether they allow using the name.
+/// Returns an error if the database failed.
+pub async fn authenticate(
+    db: &SqlitePool,
+    username: &str,
+    auth: Auth,
+) -> anyhow::Result<Result<Login, RejectReason>> {
+    // A token is only created when logging in with a password
+    let (account, token) = match auth {
+        Auth::Guest => (None, None),
+        Auth::Register { password } => {
+            if let Err(reason) = validation::validate_password(&password) {
+                return Ok(Err(reason));
+            }
+            let hash = hash_password(password).await?;
+            match database::insert_account(db, username, &hash).await? {
+                Some(id) => {
+                    info!("Registered account '{}'", username);
+                    (Some(id), None)
+                }
+                None => {
+                    return Ok(Err(RejectReason::AccountExists {
+                        name: username.to_string(),
+                    }))
+                }
+            }
+        }
+        Auth::Password { password } => {
+            let Some((id, hash)) = database::account_password(db, username).await? else {
+                return Ok(Err(RejectReason::WrongPassword));
+            };
+            if !verify_password(password, hash).await? {
+                return Ok(Err(RejectReason::WrongPassword));
+            }
+            (Some(id), None)
+        }
+        Auth::Token { token } => {
+            let used_since = chrono::Utc::now() - chrono::Duration::days(TOKEN_LIFETIME_DAYS);
+            let account = database::use_session(db, &hash_token(&token), used_since).await?;
+            // A token only logs into the account with the name it was issued for
+            match account {
+                Some(id) if database::account_id(db, username).await? == Some(id) => {
+                    (Some(id), Some(token))
+                }
+                _ => return Ok(Err(RejectReason::InvalidToken)),
+            }
+        }
+    };
+
+    if let Err(reason) = may_use_name(db, username, account).await? {
+        return Ok(Err(reason));
+    }
+    let token = match (account, token) {
+        (Some(id), None) => Some(create_token(db, id).await?),
+        (_, token) => token,
+    };
+    Ok(Ok(Login { account, token }))
+}
+
+/// Names of accounts can only be used by the account itself
+pub async fn may_use_name(
+    db: &SqlitePool,
+    name: &str,
+    account: Option<i64>,
+) -> anyhow::Result<Result<(), RejectReason>> {
+    match database::account_id(db, name).await? {
+        Some(owner) if Some(owner) != account => Ok(Err(RejectReason::RegisteredName {
+            name: name.to_string(),
+        })),
+        _ => Ok(Ok(())),
+    }
+}
+
+/// Hash a password with a random salt. Hashing is slow on purpose, so it is
+/// done outside of the async runtime.
+async fn hash_password(password: String) -> anyhow::Result<String> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| anyhow::anyhow!("Failed to hash password: {}", e))
+    })
+    .await?
+}
+
+async fn verify_password(password: String, hash: String) -> anyhow::Result<bool> {
+    tokio::task::spawn_blocking(move || {
+        let hash = PasswordHash::new(&hash)
+            .map_err(|e| anyhow::anyhow!("Invalid password hash in database: {}", e))?;
+        Ok(Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok())
+    })
+    .await?
+}
+
+/// Create a random session token for an account, only its hash is stored
+async fn create_token(db: &SqlitePool, account: i64) -> anyhow::Result<String> {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let token = to_hex(&bytes);
+    database::insert_session(db, &hash_token(&token), account).await?;
+    Ok(token)
+}
+
+/// Tokens are random enough that a fast hash is sufficient
+fn hash_token(token: &str) -> String {
+    to_hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+
+    async fn token(db: &SqlitePool, username: &str, auth: Auth) -> Result<String, RejectReason> {
+        let login = authenticate(db, username, auth).await.unwrap()?;
+        Ok(login.token.unwrap())
+    }
+
+    #[tokio::test]
+    async fn tokens_only_log_into_their_own_account() {
+        let db = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        database::migrate(&db).await.unwrap();
+        let password = "correct horse".to_string();
+        let issued = token(&db, "Alice", Auth::Register { password })
+            .await
+            .unwrap();
+
+        let auth = Auth::Token { token: issued };
+        assert!(token(&db, "alice", auth.clone()).await.is_ok());
+        assert_eq!(
+            token(&db, "bob", auth).await,
+            Err(RejectReason::InvalidToken)
+        );
+    }
+}
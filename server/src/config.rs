@@ -0,0 +1,183 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::Context;
+use clap::Parser;
+use serde::Deserialize;
+
+use crate::{app::Listener, connection::Heartbeat, tls};
+
+/// Command line arguments. Every option can also be set with an environment
+/// variable and overrides the config file.
+#[derive(Parser, Debug, Default, PartialEq, Eq)]
+#[command(version, about = "Websocket chat server")]
+pub struct Args {
+    /// TOML config file
+    #[arg(short, long, env = "CHAT_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Address to listen on
+    #[arg(long, env = "CHAT_BIND")]
+    pub bind: Option<String>,
+    /// Port to listen on
+    #[arg(short, long, env = "CHAT_PORT")]
+    pub port: Option<u16>,
+    /// Path of the SQLite database, created if it does not exist
+    #[arg(long, env = "CHAT_DATABASE")]
+    pub database: Option<PathBuf>,
+    /// Log level (error, warn, info, debug or trace), RUST_LOG takes precedence
+    #[arg(long, env = "CHAT_LOG_LEVEL")]
+    pub log_level: Option<String>,
+    /// How many messages are sent when joining a group or loading older ones
+    #[arg(long, env = "CHAT_HISTORY_SIZE", value_parser = clap::value_parser!(u32).range(1..))]
+    pub history_size: Option<u32>,
+    /// Seconds between pings to every client
+    #[arg(long, env = "CHAT_HEARTBEAT_INTERVAL", value_parser = clap::value_parser!(u64).range(1..))]
+    pub heartbeat_interval: Option<u64>,
+    /// Seconds without hearing from a client before its connection is closed
+    #[arg(long, env = "CHAT_HEARTBEAT_TIMEOUT", value_parser = clap::value_parser!(u64).range(1..))]
+    pub heartbeat_timeout: Option<u64>,
+    /// PEM certificate chain to serve wss:// with
+    #[arg(long, env = "CHAT_TLS_CERT", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+    /// PEM PKCS#8 private key of the certificate
+    #[arg(long, env = "CHAT_TLS_KEY", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+    /// Serve wss:// on this port and plain ws:// on the normal port
+    #[arg(long, env = "CHAT_TLS_PORT")]
+    pub tls_port: Option<u16>,
+}
+
+/// Settings of the server, read from the config file with the arguments
+/// applied on top
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub bind: String,
+    pub port: u16,
+    pub database: PathBuf,
+    pub log_level: String,
+    pub history_size: u32,
+    /// Seconds between pings to every client
+    pub heartbeat_interval: u64,
+    /// Seconds without hearing from a client before its connection is closed
+    pub heartbeat_timeout: u64,
+    pub tls: Option<TlsConfig>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    /// Serve TLS on this port in addition to plain ws:// on the normal port,
+    /// instead of TLS only
+    pub port: Option<u16>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1".to_string(),
+            port: 9001,
+            database: PathBuf::from("database.db"),
+            log_level: "info".to_string(),
+            history_size: 100,
+            heartbeat_interval: 15,
+            heartbeat_timeout: 45,
+            tls: None,
+        }
+    }
+}
+
+impl Config {
+    /// Read the config file named in the arguments, if any, and override it
+    /// with the arguments
+    pub fn load(args: Args) -> anyhow::Result<Self> {
+        let mut config = match args.config {
+            Some(ref path) => {
+                let content = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read config file {}", path.display()))?;
+                toml::from_str(&content)
+                    .with_context(|| format!("Invalid config file {}", path.display()))?
+            }
+            None => Config::default(),
+        };
+
+        if let Some(bind) = args.bind {
+            config.bind = bind;
+        }
+        if let Some(port) = args.port {
+            config.port = port;
+        }
+        if let Some(database) = args.database {
+            config.database = database;
+        }
+        if let Some(log_level) = args.log_level {
+            config.log_level = log_level;
+        }
+        if let Some(history_size) = args.history_size {
+            config.history_size = history_size;
+        }
+        if let Some(interval) = args.heartbeat_interval {
+            config.heartbeat_interval = interval;
+        }
+        if let Some(timeout) = args.heartbeat_timeout {
+            config.heartbeat_timeout = timeout;
+        }
+        if let (Some(cert), Some(key)) = (args.tls_cert, args.tls_key) {
+            let port = config.tls.as_ref().and_then(|tls| tls.port);
+            config.tls = Some(TlsConfig { cert, key, port });
+        }
+        if let Some(port) = args.tls_port {
+            match config.tls {
+                Some(ref mut tls) => tls.port = Some(port),
+                None => anyhow::bail!("A TLS port needs a certificate and key"),
+            }
+        }
+
+        if config.history_size == 0 {
+            anyhow::bail!("The history size must be at least 1");
+        }
+        if config.heartbeat_interval == 0 {
+            anyhow::bail!("The heartbeat interval must be at least 1 second");
+        }
+        if config.heartbeat_timeout <= config.heartbeat_interval {
+            anyhow::bail!("The heartbeat timeout must be longer than the interval");
+        }
+        Ok(config)
+    }
+
+    pub fn heartbeat(&self) -> Heartbeat {
+        Heartbeat {
+            interval: Duration::from_secs(self.heartbeat_interval),
+            timeout: Duration::from_secs(self.heartbeat_timeout),
+        }
+    }
+
+    pub fn database_url(&self) -> String {
+        format!("sqlite://{}", self.database.display())
+    }
+
+    /// The adresses to accept connections on, loading the TLS certificate if
+    /// one is configured
+    pub fn listeners(&self) -> anyhow::Result<Vec<Listener>> {
+        let adress = format!("{}:{}", self.bind, self.port);
+        let Some(ref tls) = self.tls else {
+            return Ok(vec![Listener { adress, tls: None }]);
+        };
+        let acceptor = Some(tls::load_acceptor(&tls.cert, &tls.key)?);
+
+        Ok(match tls.port {
+            Some(port) => vec![
+                Listener { adress, tls: None },
+                Listener {
+                    adress: format!("{}:{}", self.bind, port),
+                    tls: acceptor,
+                },
+            ],
+            None => vec![Listener {
+                adress,
+                tls: acceptor,
+            }],
+        })
+    }
+}
@@ -1,87 +1,270 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
+use chat_protocol::{Auth, ClientFrame, FrameError, RejectReason, ServerFrame};
 use futures_util::{SinkExt, StreamExt};
-use log::{debug, error};
-use tokio::net::TcpStream;
+use log::{debug, error, info};
+use sqlx::SqlitePool;
+use tokio::{net::TcpStream, sync::Notify};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+use crate::{auth, nicks::Nicks, validation};
+
+/// How long a client has to send its hello after connecting
+static HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Websocket over plain TCP or TLS
+pub type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Source of unique connection ids
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// How often a client is pinged and how long it may stay silent before its
+/// connection is closed
+#[derive(Debug, Clone, Copy)]
+pub struct Heartbeat {
+    pub interval: Duration,
+    pub timeout: Duration,
+}
 
 pub struct Connection {
-    pub sender: tokio::sync::mpsc::UnboundedSender<String>,
-    pub receiver: tokio::sync::mpsc::UnboundedReceiver<String>,
-    pub group: Arc<Mutex<Option<String>>>,
+    pub id: u64,
+    pub sender: tokio::sync::mpsc::UnboundedSender<ServerFrame>,
+    pub receiver: tokio::sync::mpsc::UnboundedReceiver<ClientFrame>,
+    pub username: String,
+    /// Account the client logged into, None for guests
+    pub account: Option<i64>,
+    /// Groups requested in the hello
+    pub groups: Vec<String>,
 }
 
 impl Connection {
-    pub fn new(stream: tokio_tungstenite::WebSocketStream<TcpStream>) -> Self {
+    /// Perform the handshake with a new client, check its credentials and
+    /// claim its nickname. Returns an error if the client was rejected or
+    /// disconnected before introducing itself.
+    pub async fn accept(
+        mut stream: WebSocket,
+        connected_to: String,
+        nicks: &Nicks,
+        db: &SqlitePool,
+        heartbeat: Heartbeat,
+    ) -> anyhow::Result<Self> {
+        let hello =
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, Self::read_hello(&mut stream)).await {
+                Ok(hello) => hello?,
+                Err(_) => anyhow::bail!("<{}> Timed out waiting for hello", connected_to),
+            };
+
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let kick = Arc::new(Notify::new());
+        let hello = match hello {
+            Ok((username, groups, auth)) => {
+                // Claim the name before creating the account, so nobody can
+                // start using it as a guest in the meantime
+                let registering = matches!(auth, Auth::Register { .. });
+                if registering && !nicks.claim(&username, id, None, kick.clone()) {
+                    Err(RejectReason::NickTaken { name: username })
+                } else {
+                    let login = auth::authenticate(db, &username, auth).await;
+                    if registering && !matches!(login, Ok(Ok(_))) {
+                        nicks.release(&username, id);
+                    }
+                    login?.map(|login| (username, groups, login))
+                }
+            }
+            Err(reason) => Err(reason),
+        };
+        let hello = hello.and_then(|(username, groups, login)| {
+            if nicks.claim(&username, id, login.account, kick.clone()) {
+                Ok((username, groups, login))
+            } else {
+                Err(RejectReason::NickTaken { name: username })
+            }
+        });
+        let (username, groups, login) = match hello {
+            Ok(identity) => identity,
+            Err(reason) => {
+                let frame = chat_protocol::serialize(&ServerFrame::Rejected {
+                    reason: reason.clone(),
+                });
+                stream.send(Message::Text(frame)).await?;
+                stream.close(None).await?;
+                anyhow::bail!("<{}> Rejected: {}", connected_to, reason);
+            }
+        };
+
+        let accepted = chat_protocol::serialize(&ServerFrame::Accepted { token: login.token });
+        if let Err(e) = stream.send(Message::Text(accepted)).await {
+            nicks.release(&username, id);
+            return Err(e.into());
+        }
+        match login.account {
+            Some(_) => info!("<{}> Logged into account '{}'", connected_to, username),
+            None => info!("<{}> Logged in as guest '{}'", connected_to, username),
+        }
+
+        let (sender, receiver) = Self::spawn(stream, connected_to, heartbeat, kick);
+        Ok(Self {
+            id,
+            sender,
+            receiver,
+            username,
+            account: login.account,
+            groups,
+        })
+    }
+
+    /// Wait for the hello frame and validate it
+    async fn read_hello(
+        stream: &mut WebSocket,
+    ) -> anyhow::Result<Result<(String, Vec<String>, Auth), RejectReason>> {
+        while let Some(msg) = stream.next().await {
+            let msg = match msg? {
+                Message::Text(msg) => msg,
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            debug!("Recieved hello: {}", msg);
+
+            return Ok(match chat_protocol::deserialize(&msg) {
+                Ok(ClientFrame::Hello {
+                    username,
+                    groups,
+                    auth,
+                }) => validation::validate_username(&username)
+                    .and_then(|_| {
+                        groups
+                            .iter()
+                            .try_for_each(|g| validation::validate_group(g))
+                    })
+                    .map(|_| (username, groups, auth)),
+                Err(FrameError::UnsupportedVersion(_)) => Err(RejectReason::UnsupportedVersion {
+                    supported: chat_protocol::PROTOCOL_VERSION,
+                }),
+                Ok(_) | Err(FrameError::Malformed(_)) => Err(RejectReason::ExpectedHello),
+            });
+        }
+        anyhow::bail!("Connection closed during handshake")
+    }
+
+    /// Spawn a task forwarding frames between the websocket and the returned
+    /// channels. The client is pinged regularly and the connection closed if
+    /// nothing was heard from it for the timeout of the heartbeat, or when
+    /// `kick` is notified because another login took over the name.
+    fn spawn(
+        stream: WebSocket,
+        connected_to: String,
+        heartbeat: Heartbeat,
+        kick: Arc<Notify>,
+    ) -> (
+        tokio::sync::mpsc::UnboundedSender<ServerFrame>,
+        tokio::sync::mpsc::UnboundedReceiver<ClientFrame>,
+    ) {
         let (tx_read, rx_read) = tokio::sync::mpsc::unbounded_channel();
         let (tx_write, mut rx_write) = tokio::sync::mpsc::unbounded_channel();
 
-        let group = Arc::new(Mutex::new(None));
-
-        let g_clone = group.clone();
         tokio::spawn(async move {
-            let connected_to = stream.get_ref().peer_addr().unwrap().to_string();
             let (mut ws_write, mut ws_read) = stream.split();
+            let mut ping = tokio::time::interval(heartbeat.interval);
+            // Any message shows the client is still there, not only pongs
+            let mut last_seen = Instant::now();
 
             loop {
+                let deadline = last_seen + heartbeat.timeout;
                 tokio::select! {
-                    Some(msg) = rx_write.recv() => {
-
+                    frame = rx_write.recv() => {
+                        // The session ended
+                        let Some(frame) = frame else {
+                            break;
+                        };
+                        let msg = chat_protocol::serialize(&frame);
                         debug!("<{}> Sending message: {}", connected_to, msg);
-                        ws_write.send(tokio_tungstenite::tungstenite::Message::Text(msg)).await.unwrap();
+                        if let Err(e) = ws_write.send(Message::Text(msg)).await {
+                            error!("<{}> Error writing to websocket: {}", connected_to, e);
+                            break;
+                        }
                     }
-                    Some(msg) = ws_read.next() => {
-                        match msg {
-                            Ok(msg) => {
-                                let msg = msg.into_text().expect("Failed to convert message to text");
-                                debug!("<{}> Recieved message: {}", connected_to, msg);
-
-                                if g_clone.lock().unwrap().is_none() {
-                                    *g_clone.lock().unwrap() = Some(msg.clone());
-                                    debug!("<{}> Joined group: {}", connected_to, msg);
-                                } else {
-                                    tx_read.send(msg).unwrap();
+                    msg = ws_read.next() => {
+                        let msg = match msg {
+                            Some(Ok(msg)) => msg,
+                            // Closed by the client
+                            None => break,
+                            Some(Err(e)) => {
+                                error!("<{}> Error reading from websocket: {}", connected_to, e);
+                                break;
+                            }
+                        };
+                        last_seen = Instant::now();
+                        let msg = match msg {
+                            Message::Text(msg) => msg,
+                            Message::Close(_) => break,
+                            // Pings are answered by tungstenite
+                            _ => continue,
+                        };
+                        debug!("<{}> Recieved message: {}", connected_to, msg);
+
+                        match chat_protocol::deserialize::<ClientFrame>(&msg) {
+                            Ok(frame) => {
+                                // The session ended
+                                if tx_read.send(frame).is_err() {
+                                    break;
                                 }
                             }
                             Err(e) => {
-                                error!("<{}> Error reading from websocket: {}", connected_to, e);
-                                break;
+                                error!("<{}> Failed to deserialize message: {}", connected_to, e);
+                                let error = ServerFrame::Error { reason: e.to_string() };
+                                if let Err(e) = ws_write.send(Message::Text(chat_protocol::serialize(&error))).await {
+                                    error!("<{}> Error writing to websocket: {}", connected_to, e);
+                                    break;
+                                }
                             }
                         }
                     }
+                    _ = ping.tick() => {
+                        if let Err(e) = ws_write.send(Message::Ping(Vec::new())).await {
+                            error!("<{}> Error sending ping: {}", connected_to, e);
+                            break;
+                        }
+                    }
+                    _ = tokio::time::sleep_until(deadline.into()) => {
+                        info!(
+                            "<{}> No answer for {:?}, closing the connection",
+                            connected_to, heartbeat.timeout
+                        );
+                        ws_write.close().await.ok();
+                        break;
+                    }
+                    _ = kick.notified() => {
+                        info!("<{}> Logged in again elsewhere, closing the connection", connected_to);
+                        ws_write.close().await.ok();
+                        break;
+                    }
                 }
             }
         });
-        Self {
-            sender: tx_write,
-            receiver: rx_read,
-            group,
-        }
-    }
-
-    pub async fn wait_for_group(&self) -> String {
-        loop {
-            if let Some(group) = self.group.lock().unwrap().as_ref() {
-                return group.clone();
-            }
-            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-        }
+        (tx_write, rx_read)
     }
 
     pub fn send(
-        sender: &tokio::sync::mpsc::UnboundedSender<String>,
-        msg: String,
+        sender: &tokio::sync::mpsc::UnboundedSender<ServerFrame>,
+        frame: ServerFrame,
     ) -> anyhow::Result<()> {
-        sender.send(msg)?;
+        sender.send(frame)?;
         Ok(())
     }
 
     pub async fn recieve(
-        mut reciever: tokio::sync::mpsc::UnboundedReceiver<String>,
-    ) -> anyhow::Result<String> {
-        let msg = reciever
+        mut reciever: tokio::sync::mpsc::UnboundedReceiver<ClientFrame>,
+    ) -> anyhow::Result<ClientFrame> {
+        let frame = reciever
             .recv()
             .await
             .ok_or_else(|| anyhow::anyhow!("Failed to recieve message"))?;
-        Ok(msg)
+        Ok(frame)
     }
 }
@@ -1,9 +1,22 @@
 use log::info;
-use sqlx::{migrate::MigrateDatabase, sqlite::SqlitePoolOptions, Pool, Sqlite};
+use sqlx::{
+    migrate::{MigrateDatabase, Migrator},
+    sqlite::SqlitePoolOptions,
+    Pool, Sqlite,
+};
 
-use crate::ChatMessage;
+use chat_protocol::ChatMessage;
+use chrono::{DateTime, Utc};
 
-static MESSAGE_RETRIVAL_AMOUNT: u32 = 100;
+/// Id, username, message, action flag, timestamp and client key of a stored message
+type MessageRow = (
+    i64,
+    String,
+    String,
+    bool,
+    Option<DateTime<Utc>>,
+    Option<String>,
+);
 
 pub async fn establish_connection(database_url: &str) -> anyhow::Result<Pool<Sqlite>> {
     // Create database if needed
@@ -19,63 +32,352 @@ pub async fn establish_connection(database_url: &str) -> anyhow::Result<Pool<Sql
         .map_err(|e| e.into())
 }
 
-pub async fn create_table(pool: &Pool<Sqlite>) {
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS messages (
-            id INTEGER PRIMARY KEY,
-            group_name TEXT NOT NULL,
-            username TEXT NOT NULL,
-            message TEXT NOT NULL
-        )
-        "#,
-    )
-    .execute(pool)
-    .await
-    .expect("Failed to create table");
+/// Migrations embedded from the `migrations` directory
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Bring the database schema up to date. Refuses to touch databases that were
+/// migrated by a newer version of the server.
+pub async fn migrate(pool: &Pool<Sqlite>) -> anyhow::Result<()> {
+    let latest = MIGRATOR.iter().map(|m| m.version).max().unwrap_or(0);
+
+    match schema_version(pool).await? {
+        None => upgrade_legacy(pool).await?,
+        Some(version) if version > latest => anyhow::bail!(
+            "Database schema version {} is newer than the newest version this server supports ({})",
+            version,
+            latest
+        ),
+        Some(_) => {}
+    }
+
+    MIGRATOR.run(pool).await?;
+    info!("Database schema version {}", latest);
 
     // Count number of messages
     let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM messages")
         .fetch_one(pool)
-        .await
-        .expect("Failed to count messages");
+        .await?;
     info!("Database contains {} messages", count);
+    Ok(())
 }
 
-pub async fn insert_message(pool: &Pool<Sqlite>, group_name: &str, message: &ChatMessage) {
-    sqlx::query(
+/// The newest migration applied to the database, None if it was never migrated
+async fn schema_version(pool: &Pool<Sqlite>) -> anyhow::Result<Option<i64>> {
+    let migrated: bool = sqlx::query_scalar(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'",
+    )
+    .fetch_one(pool)
+    .await?;
+    if !migrated {
+        return Ok(None);
+    }
+
+    let version = sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations")
+        .fetch_one(pool)
+        .await?;
+    Ok(version)
+}
+
+/// Databases created before migrations were introduced may lack the timestamp
+/// column of the first migration, which only creates the table if it is missing
+async fn upgrade_legacy(pool: &Pool<Sqlite>) -> anyhow::Result<()> {
+    let columns: Vec<String> = sqlx::query_scalar("SELECT name FROM pragma_table_info('messages')")
+        .fetch_all(pool)
+        .await?;
+    if !columns.is_empty() && !columns.iter().any(|c| c == "created_at") {
+        info!("Adding timestamp column to messages");
+        sqlx::query("ALTER TABLE messages ADD COLUMN created_at TEXT")
+            .execute(pool)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Store a message and return the id assigned to it
+pub async fn insert_message(
+    pool: &Pool<Sqlite>,
+    group_name: &str,
+    message: &ChatMessage,
+) -> anyhow::Result<i64> {
+    let result = sqlx::query(
         r#"
-        INSERT INTO messages (group_name, username, message)
-        VALUES (?, ?, ?)
+        INSERT INTO messages (group_name, username, message, action, created_at, client_key)
+        VALUES (?, ?, ?, ?, ?, ?)
         "#,
     )
     .bind(group_name)
     .bind(&message.username)
     .bind(&message.message)
+    .bind(message.action)
+    .bind(message.timestamp)
+    .bind(&message.key)
     .execute(pool)
-    .await
-    .expect("Failed to insert message");
+    .await?;
+    Ok(result.last_insert_rowid())
 }
 
-pub async fn get_messages(pool: &Pool<Sqlite>, group_name: &str) -> Vec<ChatMessage> {
-    sqlx::query_as(
+/// Get the message of a group that was sent with the given key
+pub async fn get_message_by_key(
+    pool: &Pool<Sqlite>,
+    group_name: &str,
+    key: &str,
+) -> anyhow::Result<Option<ChatMessage>> {
+    let message: Option<MessageRow> = sqlx::query_as(
         r#"
-        SELECT username, message
+        SELECT id, username, message, action, created_at, client_key
         FROM messages
-        WHERE group_name = ?
+        WHERE group_name = ? AND client_key = ?
+        "#,
+    )
+    .bind(group_name)
+    .bind(key)
+    .fetch_optional(pool)
+    .await?;
+    Ok(message.map(to_message))
+}
+
+/// Get the messages of a group newer than `since`, oldest first. Returns None
+/// if there are more than `limit` of them.
+pub async fn get_messages_since(
+    pool: &Pool<Sqlite>,
+    group_name: &str,
+    since: i64,
+    limit: u32,
+) -> anyhow::Result<Option<Vec<ChatMessage>>> {
+    let messages: Vec<MessageRow> = sqlx::query_as(
+        r#"
+        SELECT id, username, message, action, created_at, client_key
+        FROM messages
+        WHERE group_name = ? AND id > ?
         ORDER BY id ASC
         LIMIT ?
         "#,
     )
     .bind(group_name)
-    .bind(MESSAGE_RETRIVAL_AMOUNT)
+    .bind(since)
+    // One more than allowed, to know if there are too many
+    .bind(limit + 1)
     .fetch_all(pool)
-    .await
-    .map(|messages: Vec<(String, String)>| {
-        messages
-            .into_iter()
-            .map(|(username, message)| ChatMessage { username, message })
-            .collect()
-    })
-    .expect("Failed to fetch messages")
+    .await?;
+
+    Ok((messages.len() <= limit as usize).then(|| messages.into_iter().map(to_message).collect()))
+}
+
+/// Get the newest `limit` messages of a group in chronological order, only messages
+/// older than `before` if it is given. Also returns whether there are even older messages.
+pub async fn get_messages(
+    pool: &Pool<Sqlite>,
+    group_name: &str,
+    before: Option<i64>,
+    limit: u32,
+) -> anyhow::Result<(Vec<ChatMessage>, bool)> {
+    let messages: Vec<MessageRow> = sqlx::query_as(
+        r#"
+        SELECT id, username, message, action, created_at, client_key
+        FROM messages
+        WHERE group_name = ? AND id < ?
+        ORDER BY id DESC
+        LIMIT ?
+        "#,
+    )
+    .bind(group_name)
+    .bind(before.unwrap_or(i64::MAX))
+    // One more than needed, to know if there are older messages
+    .bind(limit + 1)
+    .fetch_all(pool)
+    .await?;
+
+    let has_more = messages.len() > limit as usize;
+    let mut messages: Vec<ChatMessage> = messages
+        .into_iter()
+        .take(limit as usize)
+        .map(to_message)
+        .collect();
+    messages.reverse();
+    Ok((messages, has_more))
+}
+
+/// Id of the account with the given name, names are compared case-insensitively
+pub async fn account_id(pool: &Pool<Sqlite>, name: &str) -> anyhow::Result<Option<i64>> {
+    let id = sqlx::query_scalar("SELECT id FROM accounts WHERE name = ?")
+        .bind(name)
+        .fetch_optional(pool)
+        .await?;
+    Ok(id)
+}
+
+/// Id and password hash of the account with the given name
+pub async fn account_password(
+    pool: &Pool<Sqlite>,
+    name: &str,
+) -> anyhow::Result<Option<(i64, String)>> {
+    let account = sqlx::query_as("SELECT id, password_hash FROM accounts WHERE name = ?")
+        .bind(name)
+        .fetch_optional(pool)
+        .await?;
+    Ok(account)
+}
+
+/// Create an account and return its id, None if the name is already taken
+pub async fn insert_account(
+    pool: &Pool<Sqlite>,
+    name: &str,
+    password_hash: &str,
+) -> anyhow::Result<Option<i64>> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO accounts (name, password_hash, created_at)
+        VALUES (?, ?, ?)
+        ON CONFLICT (name) DO NOTHING
+        "#,
+    )
+    .bind(name)
+    .bind(password_hash)
+    .bind(Utc::now())
+    .execute(pool)
+    .await?;
+    Ok((result.rows_affected() > 0).then(|| result.last_insert_rowid()))
+}
+
+/// Store the hash of a new session token
+pub async fn insert_session(
+    pool: &Pool<Sqlite>,
+    token_hash: &str,
+    account_id: i64,
+) -> anyhow::Result<()> {
+    let now = Utc::now();
+    sqlx::query(
+        r#"
+        INSERT INTO sessions (token_hash, account_id, created_at, last_used_at)
+        VALUES (?, ?, ?, ?)
+        "#,
+    )
+    .bind(token_hash)
+    .bind(account_id)
+    .bind(now)
+    .bind(now)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Account of a session that was used after `used_since`, marking it as used now
+pub async fn use_session(
+    pool: &Pool<Sqlite>,
+    token_hash: &str,
+    used_since: DateTime<Utc>,
+) -> anyhow::Result<Option<i64>> {
+    let account_id = sqlx::query_scalar(
+        r#"
+        UPDATE sessions SET last_used_at = ?
+        WHERE token_hash = ? AND last_used_at > ?
+        RETURNING account_id
+        "#,
+    )
+    .bind(Utc::now())
+    .bind(token_hash)
+    .bind(used_since)
+    .fetch_optional(pool)
+    .await?;
+    Ok(account_id)
+}
+
+fn to_message((id, username, message, action, timestamp, key): MessageRow) -> ChatMessage {
+    ChatMessage {
+        id: Some(id),
+        username,
+        message,
+        action,
+        timestamp,
+        key,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh database in memory, every connection would get its own
+    async fn database() -> Pool<Sqlite> {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        migrate(&pool).await.unwrap();
+        pool
+    }
+
+    /// Store messages numbered from 1 to `count` and return their ids
+    async fn insert(pool: &Pool<Sqlite>, group: &str, count: usize) -> Vec<i64> {
+        let mut ids = Vec::new();
+        for n in 1..=count {
+            let message = ChatMessage {
+                id: None,
+                username: "alice".to_string(),
+                message: n.to_string(),
+                action: false,
+                timestamp: None,
+                key: None,
+            };
+            ids.push(insert_message(pool, group, &message).await.unwrap());
+        }
+        ids
+    }
+
+    fn texts(messages: &[ChatMessage]) -> Vec<&str> {
+        messages.iter().map(|m| m.message.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn exactly_limit_messages_have_no_more() {
+        let pool = database().await;
+        insert(&pool, "lobby", 3).await;
+        insert(&pool, "other", 2).await;
+
+        let (messages, has_more) = get_messages(&pool, "lobby", None, 3).await.unwrap();
+        assert_eq!(texts(&messages), ["1", "2", "3"]);
+        assert!(!has_more);
+    }
+
+    #[tokio::test]
+    async fn one_more_than_limit_has_more() {
+        let pool = database().await;
+        insert(&pool, "lobby", 4).await;
+
+        let (messages, has_more) = get_messages(&pool, "lobby", None, 3).await.unwrap();
+        assert_eq!(texts(&messages), ["2", "3", "4"]);
+        assert!(has_more);
+    }
+
+    #[tokio::test]
+    async fn before_excludes_the_given_message() {
+        let pool = database().await;
+        let ids = insert(&pool, "lobby", 5).await;
+
+        let (messages, has_more) = get_messages(&pool, "lobby", Some(ids[3]), 2).await.unwrap();
+        assert_eq!(texts(&messages), ["2", "3"]);
+        assert!(has_more);
+
+        let (messages, has_more) = get_messages(&pool, "lobby", Some(ids[2]), 2).await.unwrap();
+        assert_eq!(texts(&messages), ["1", "2"]);
+        assert!(!has_more);
+    }
+
+    #[tokio::test]
+    async fn since_gives_up_on_too_many_messages() {
+        let pool = database().await;
+        let ids = insert(&pool, "lobby", 5).await;
+
+        let messages = get_messages_since(&pool, "lobby", ids[1], 3).await.unwrap();
+        assert_eq!(texts(&messages.unwrap()), ["3", "4", "5"]);
+        assert_eq!(
+            get_messages_since(&pool, "lobby", ids[0], 3).await.unwrap(),
+            None
+        );
+        assert_eq!(
+            get_messages_since(&pool, "lobby", ids[4], 3).await.unwrap(),
+            Some(Vec::new())
+        );
+    }
 }
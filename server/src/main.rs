@@ -1,29 +1,72 @@
-use chat_server::app::Application;
+use std::io::IsTerminal;
+
+use chat_server::{
+    app::Application,
+    config::{Args, Config},
+};
+use clap::Parser;
 use futures_util::FutureExt;
-use log::info;
+use log::{error, info};
 
 #[tokio::main]
 async fn main() {
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
-
-    // Get Port
-    let mut port = String::new();
-    println!("Enter server port (leave empty for default value '9001'): ");
-    std::io::stdin().read_line(&mut port).unwrap();
-    let adress = match port.trim() {
-        "" => "127.0.0.1:9001".to_string(),
-        port => format!("127.0.0.1:{}", port),
+    let mut args = Args::parse();
+
+    // Only ask for the port when started by hand without any options
+    if args == Args::default() && std::io::stdin().is_terminal() {
+        args.port = prompt_port();
+    }
+
+    let config = match Config::load(args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{:#}", e);
+            std::process::exit(2);
+        }
     };
 
-    let mut app = Application::new(&adress);
+    env_logger::Builder::from_env(
+        env_logger::Env::default().default_filter_or(config.log_level.as_str()),
+    )
+    .init();
+
+    let mut app = match Application::new(&config) {
+        Ok(app) => app,
+        Err(e) => {
+            error!("{:#}", e);
+            std::process::exit(1);
+        }
+    };
 
     info!("Started");
     tokio::select! {
         _ = tokio::signal::ctrl_c() => {
             info!("Ctrl-C recieved");
         }
-        r = app.run().fuse() => {info!("Websocket task ended with: {:?}", r)},
+        r = app.run().fuse() => match r {
+            Ok(()) => info!("Websocket task ended"),
+            Err(e) => {
+                error!("Server stopped: {:#}", e);
+                std::process::exit(1);
+            }
+        },
     }
 
     info!("Exiting");
 }
+
+/// Ask for the port, None keeps the default
+fn prompt_port() -> Option<u16> {
+    loop {
+        let mut port = String::new();
+        println!("Enter server port (leave empty for default value '9001'): ");
+        std::io::stdin().read_line(&mut port).unwrap();
+        match port.trim() {
+            "" => return None,
+            port => match port.parse() {
+                Ok(port) => return Some(port),
+                Err(_) => println!("Not a valid port"),
+            },
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::Notify;
+
+/// Nicknames in use on the server. Names are compared case-insensitively, so
+/// nobody can pose as someone else by changing the case of a letter.
+#[derive(Clone, Default)]
+pub struct Nicks(Arc<Mutex<HashMap<String, Holder>>>);
+
+/// The connection using a nickname
+struct Holder {
+    id: u64,
+    /// Account the connection logged into, None for guests
+    account: Option<i64>,
+    /// Closes the connection when another login of the account takes over
+    kick: Arc<Notify>,
+}
+
+impl Nicks {
+    /// Reserve a nickname for a connection, returns false if it is in use by
+    /// someone else. A login to the same account takes the name over from
+    /// the connection holding it, which is closed. That connection is usually
+    /// one the client lost and the server did not notice yet. Claiming again
+    /// for the same connection records the account it logged into.
+    pub fn claim(&self, nick: &str, id: u64, account: Option<i64>, kick: Arc<Notify>) -> bool {
+        let holder = Holder { id, account, kick };
+        match self.0.lock().unwrap().entry(key(nick)) {
+            Entry::Vacant(entry) => {
+                entry.insert(holder);
+                true
+            }
+            Entry::Occupied(mut entry) if entry.get().id == id => {
+                entry.insert(holder);
+                true
+            }
+            Entry::Occupied(mut entry) if account.is_some() && entry.get().account == account => {
+                entry.insert(holder).kick.notify_one();
+                true
+            }
+            Entry::Occupied(_) => false,
+        }
+    }
+
+    /// Give up a nickname so others can use it, unless another connection
+    /// took it over in the meantime
+    pub fn release(&self, nick: &str, id: u64) {
+        let mut nicks = self.0.lock().unwrap();
+        if let Entry::Occupied(entry) = nicks.entry(key(nick)) {
+            if entry.get().id == id {
+                entry.remove();
+            }
+        }
+    }
+
+    /// Exchange a nickname for another one, returns false and keeps the old
+    /// one if the new one is in use by someone else
+    pub fn rename(&self, old: &str, new: &str, id: u64) -> bool {
+        let (old, new) = (key(old), key(new));
+        if old == new {
+            return true;
+        }
+        let mut nicks = self.0.lock().unwrap();
+        if nicks.contains_key(&new) {
+            return false;
+        }
+        match nicks.remove(&old) {
+            Some(holder) if holder.id == id => {
+                nicks.insert(new, holder);
+                true
+            }
+            // Taken over by another login, it is not ours to give away
+            Some(holder) => {
+                nicks.insert(old, holder);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+fn key(nick: &str) -> String {
+    nick.to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kick() -> Arc<Notify> {
+        Arc::new(Notify::new())
+    }
+
+    #[test]
+    fn names_differing_in_case_are_the_same() {
+        let nicks = Nicks::default();
+        assert!(nicks.claim("Alice", 1, None, kick()));
+        assert!(!nicks.claim("alice", 2, None, kick()));
+        assert!(!nicks.claim("ALICE", 2, Some(7), kick()));
+    }
+
+    #[test]
+    fn rename_keeps_the_old_name_on_conflict() {
+        let nicks = Nicks::default();
+        assert!(nicks.claim("alice", 1, None, kick()));
+        assert!(nicks.claim("bob", 2, None, kick()));
+        assert!(!nicks.rename("alice", "BOB", 1));
+        assert!(!nicks.claim("alice", 3, None, kick()));
+
+        // Changing the case of the own name is no conflict
+        assert!(nicks.rename("alice", "Alice", 1));
+        assert!(nicks.rename("alice", "carol", 1));
+        assert!(nicks.claim("alice", 3, None, kick()));
+    }
+
+    #[test]
+    fn release_by_another_connection_is_ignored() {
+        let nicks = Nicks::default();
+        assert!(nicks.claim("alice", 1, None, kick()));
+        nicks.release("alice", 2);
+        assert!(!nicks.claim("alice", 2, None, kick()));
+        nicks.release("alice", 1);
+        assert!(nicks.claim("alice", 2, None, kick()));
+    }
+
+    #[tokio::test]
+    async fn login_to_the_same_account_takes_over() {
+        let nicks = Nicks::default();
+        let first = kick();
+        assert!(nicks.claim("alice", 1, Some(7), first.clone()));
+        assert!(!nicks.claim("alice", 2, Some(8), kick()));
+        assert!(nicks.claim("alice", 2, Some(7), kick()));
+        // Completes right away, the permit was stored when taking over
+        first.notified().await;
+
+        // The old connection can not give away the name any more
+        nicks.release("alice", 1);
+        assert!(!nicks.claim("alice", 3, None, kick()));
+    }
+}
@@ -0,0 +1,78 @@
+use std::time::{Duration, Instant};
+
+/// How many messages a client can send in a row
+static BURST: f64 = 10.0;
+/// How long it takes until the client can send one more message
+static REFILL: Duration = Duration::from_secs(1);
+
+/// Token bucket limiting how fast a client sends messages. Every message
+/// takes a token, tokens come back over time up to the burst size.
+pub struct RateLimit {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            tokens: BURST,
+            updated: Instant::now(),
+        }
+    }
+}
+
+impl RateLimit {
+    /// Take a token, returns false if there is none left
+    pub fn allow(&mut self) -> bool {
+        self.allow_at(Instant::now())
+    }
+
+    fn allow_at(&mut self, now: Instant) -> bool {
+        let refilled = now.duration_since(self.updated).as_secs_f64() / REFILL.as_secs_f64();
+        self.tokens = (self.tokens + refilled).min(BURST);
+        self.updated = now;
+
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_more_than_the_burst() {
+        let start = Instant::now();
+        let mut limit = RateLimit {
+            tokens: BURST,
+            updated: start,
+        };
+        for _ in 0..10 {
+            assert!(limit.allow_at(start));
+        }
+        assert!(!limit.allow_at(start));
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let start = Instant::now();
+        let mut limit = RateLimit {
+            tokens: 0.0,
+            updated: start,
+        };
+        assert!(!limit.allow_at(start + REFILL / 2));
+        assert!(limit.allow_at(start + REFILL));
+        assert!(!limit.allow_at(start + REFILL));
+
+        // Waiting long does not allow more than the burst
+        let later = start + REFILL * 100;
+        for _ in 0..10 {
+            assert!(limit.allow_at(later));
+        }
+        assert!(!limit.allow_at(later));
+    }
+}
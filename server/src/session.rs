@@ -0,0 +1,350 @@
+use std::collections::HashSet;
+
+use chat_protocol::{ChatMessage, ClientFrame, RejectReason, ServerFrame};
+use log::{debug, error, info};
+
+use crate::{
+    app::{Groups, Sender},
+    auth,
+    connection::Connection,
+    database,
+    nicks::Nicks,
+    rate_limit::RateLimit,
+    validation,
+};
+
+/// State of a single logged in client
+pub struct Session {
+    pub id: u64,
+    pub username: String,
+    /// Account the client logged into, None for guests
+    pub account: Option<i64>,
+    pub sender: Sender,
+    /// Groups this client is a member of
+    pub joined: HashSet<String>,
+    pub groups: Groups,
+    pub nicks: Nicks,
+    pub db: Option<sqlx::SqlitePool>,
+    /// How many messages are sent at once from the database
+    pub history_size: u32,
+    /// How fast the client may send messages
+    pub rate_limit: RateLimit,
+}
+
+impl Session {
+    /// Handle frames from the client until the connection closes
+    pub async fn run(mut self, connection: Connection) {
+        let mut receiver = connection.receiver;
+
+        for group in connection.groups {
+            self.join(group, None).await;
+        }
+
+        while let Some(frame) = receiver.recv().await {
+            debug!("Recieved frame from {}: {:?}", self.username, frame);
+
+            match frame {
+                ClientFrame::Hello { .. } => {
+                    error!("Already introduced, ignoring hello");
+                }
+                ClientFrame::Join { group, since } => self.join(group, since).await,
+                ClientFrame::Part { group } => self.part(group),
+                ClientFrame::Chat {
+                    group,
+                    message,
+                    action,
+                    key,
+                } => self.chat(group, message, action, key).await,
+                ClientFrame::LoadOlder { group, before } => self.load_older(group, before).await,
+                ClientFrame::Nick { nick } => self.nick(nick).await,
+            }
+        }
+
+        info!("Connection of '{}' closed", self.username);
+        for group in std::mem::take(&mut self.joined) {
+            self.leave(&group);
+        }
+        self.nicks.release(&self.username, self.id);
+    }
+
+    /// Join a group and send its messages, only the ones after `since` if
+    /// the client resumes after reconnecting and did not miss too many
+    async fn join(&mut self, group: String, since: Option<i64>) {
+        if let Err(reason) = validation::validate_group(&group) {
+            self.send(ServerFrame::JoinFailed { group, reason });
+            return;
+        }
+        if self.joined.contains(&group) {
+            let reason = RejectReason::AlreadyJoined {
+                group: group.clone(),
+            };
+            self.send(ServerFrame::JoinFailed { group, reason });
+            return;
+        }
+
+        self.send(ServerFrame::Joined {
+            group: group.clone(),
+        });
+
+        // Become a member before reading the database, so no message is
+        // missed in between. The client skips the ones it gets twice.
+        self.groups
+            .lock()
+            .unwrap()
+            .entry(group.clone())
+            .or_default()
+            .insert(self.id, self.sender.clone());
+        self.joined.insert(group.clone());
+
+        // Send old messages from the database
+        if let Some(ref db) = self.db {
+            match Self::history(db, group.clone(), since, self.history_size).await {
+                Ok(frame) => self.send(frame),
+                Err(e) => {
+                    error!("Failed to load the messages of '{}': {}", group, e);
+                    self.send(ServerFrame::Error {
+                        reason: format!("Could not load the messages of '{}'", group),
+                    });
+                }
+            }
+        }
+    }
+
+    /// The history sent after joining a group
+    async fn history(
+        db: &sqlx::SqlitePool,
+        group: String,
+        since: Option<i64>,
+        history_size: u32,
+    ) -> anyhow::Result<ServerFrame> {
+        let missed = match since {
+            Some(since) => database::get_messages_since(db, &group, since, history_size).await?,
+            None => None,
+        };
+        Ok(match missed {
+            Some(messages) => {
+                info!(
+                    "Sending {} missed messages from group '{}'",
+                    messages.len(),
+                    group
+                );
+                ServerFrame::History {
+                    group,
+                    messages,
+                    has_more: false,
+                    since,
+                }
+            }
+            None => {
+                let (messages, has_more) =
+                    database::get_messages(db, &group, None, history_size).await?;
+                info!("Sending {} messages from group '{}'", messages.len(), group);
+                ServerFrame::History {
+                    group,
+                    messages,
+                    has_more,
+                    since: None,
+                }
+            }
+        })
+    }
+
+    fn part(&mut self, group: String) {
+        if self.joined.remove(&group) {
+            self.leave(&group);
+        }
+        self.send(ServerFrame::Parted { group });
+    }
+
+    /// Store a message and send it to everyone in the group, then tell the
+    /// sender whether it was delivered
+    async fn chat(&mut self, group: String, text: String, action: bool, key: Option<String>) {
+        let frame = match self.deliver(&group, text, action, key.clone()).await {
+            Ok(id) => ServerFrame::Delivered { group, key, id },
+            Err(reason) => {
+                info!("Message from '{}' not delivered: {}", self.username, reason);
+                ServerFrame::Undelivered { group, key, reason }
+            }
+        };
+        self.send(frame);
+    }
+
+    /// Returns the id the message is stored with
+    async fn deliver(
+        &mut self,
+        group: &str,
+        text: String,
+        action: bool,
+        key: Option<String>,
+    ) -> Result<i64, RejectReason> {
+        if !self.joined.contains(group) {
+            return Err(RejectReason::NotMember {
+                group: group.to_string(),
+            });
+        }
+        validation::validate_message(&text)?;
+        let Some(ref db) = self.db else {
+            return Err(RejectReason::NotStored);
+        };
+
+        // Sent again after reconnecting, the others already have it
+        if let Some(ref key) = key {
+            match database::get_message_by_key(db, group, key).await {
+                Ok(Some(message)) => {
+                    debug!("Message with key {} is already stored", key);
+                    let id = message.id.unwrap_or_default();
+                    self.send(ServerFrame::Chat {
+                        group: group.to_string(),
+                        message,
+                    });
+                    return Ok(id);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    error!("Failed to look up message with key {}: {}", key, e);
+                    return Err(RejectReason::NotStored);
+                }
+            }
+        }
+        if !self.rate_limit.allow() {
+            return Err(RejectReason::RateLimited);
+        }
+
+        debug!("Message from {}: {}", self.username, text);
+        // The client only sends the text, who sent it is known from the connection
+        let mut message = ChatMessage {
+            id: None,
+            username: self.username.clone(),
+            message: text,
+            action,
+            timestamp: Some(chrono::Utc::now()),
+            key,
+        };
+
+        // Save message to database
+        let id = database::insert_message(db, group, &message)
+            .await
+            .map_err(|e| {
+                error!("Failed to store message: {}", e);
+                RejectReason::NotStored
+            })?;
+        message.id = Some(id);
+
+        // Send message to everyone in the group
+        let mut groups = self.groups.lock().unwrap();
+        if let Some(members) = groups.get_mut(group) {
+            members.retain(|_, c| {
+                let frame = ServerFrame::Chat {
+                    group: group.to_string(),
+                    message: message.clone(),
+                };
+                if let Err(e) = Connection::send(c, frame) {
+                    error!("Error sending message: {}", e);
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+        Ok(id)
+    }
+
+    async fn load_older(&mut self, group: String, before: i64) {
+        if !self.joined.contains(&group) {
+            self.send(ServerFrame::Error {
+                reason: format!("Not a member of '{}'", group),
+            });
+            return;
+        }
+
+        let (messages, has_more) = match self.db {
+            Some(ref db) => {
+                match database::get_messages(db, &group, Some(before), self.history_size).await {
+                    Ok(page) => page,
+                    Err(e) => {
+                        error!("Failed to load older messages of '{}': {}", group, e);
+                        self.send(ServerFrame::Error {
+                            reason: format!("Could not load older messages of '{}'", group),
+                        });
+                        // Let the client try again
+                        (Vec::new(), true)
+                    }
+                }
+            }
+            None => (Vec::new(), false),
+        };
+        debug!(
+            "Sending {} messages before {} from group '{}'",
+            messages.len(),
+            before,
+            group
+        );
+        self.send(ServerFrame::Older {
+            group,
+            messages,
+            has_more,
+        });
+    }
+
+    async fn nick(&mut self, nick: String) {
+        if let Err(reason) = validation::validate_username(&nick) {
+            self.send(ServerFrame::NickFailed { nick, reason });
+            return;
+        }
+        if let Some(ref db) = self.db {
+            match auth::may_use_name(db, &nick, self.account).await {
+                Ok(Ok(())) => {}
+                Ok(Err(reason)) => {
+                    self.send(ServerFrame::NickFailed { nick, reason });
+                    return;
+                }
+                Err(e) => {
+                    error!("Failed to look up account '{}': {}", nick, e);
+                    self.send(ServerFrame::Error {
+                        reason: format!("Could not change name to '{}'", nick),
+                    });
+                    return;
+                }
+            }
+        }
+        if !self.nicks.rename(&self.username, &nick, self.id) {
+            let reason = RejectReason::NickTaken { name: nick.clone() };
+            self.send(ServerFrame::NickFailed { nick, reason });
+            return;
+        }
+        let old = std::mem::replace(&mut self.username, nick.clone());
+        info!("'{}' is now known as '{}'", old, nick);
+
+        // Tell everyone sharing a group once, including this client
+        let frame = ServerFrame::NickChanged { old, new: nick };
+        let mut notified = HashSet::from([self.id]);
+        self.send(frame.clone());
+        let groups = self.groups.lock().unwrap();
+        for group in &self.joined {
+            for (id, sender) in groups.get(group).into_iter().flatten() {
+                if notified.insert(*id) {
+                    if let Err(e) = Connection::send(sender, frame.clone()) {
+                        error!("Error sending message: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Remove this connection from the members of a group
+    fn leave(&self, group: &str) {
+        let mut groups = self.groups.lock().unwrap();
+        if let Some(members) = groups.get_mut(group) {
+            members.remove(&self.id);
+            if members.is_empty() {
+                groups.remove(group);
+            }
+        }
+    }
+
+    fn send(&self, frame: ServerFrame) {
+        if let Err(e) = Connection::send(&self.sender, frame) {
+            error!("Error sending message: {}", e);
+        }
+    }
+}
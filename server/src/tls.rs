@@ -0,0 +1,18 @@
+use std::path::Path;
+
+use anyhow::Context;
+use native_tls::Identity;
+use tokio_native_tls::TlsAcceptor;
+
+/// Create a TLS acceptor from a PEM certificate chain and a PEM PKCS#8 private key
+pub fn load_acceptor(cert: &Path, key: &Path) -> anyhow::Result<TlsAcceptor> {
+    let cert = std::fs::read(cert)
+        .with_context(|| format!("Failed to read certificate {}", cert.display()))?;
+    let key =
+        std::fs::read(key).with_context(|| format!("Failed to read key {}", key.display()))?;
+
+    let identity =
+        Identity::from_pkcs8(&cert, &key).context("Failed to load certificate and key")?;
+    let acceptor = native_tls::TlsAcceptor::new(identity).context("Failed to set up TLS")?;
+    Ok(acceptor.into())
+}
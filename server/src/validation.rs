@@ -0,0 +1,78 @@
+use chat_protocol::RejectReason;
+
+static MAX_NAME_LENGTH: usize = 32;
+static MIN_PASSWORD_LENGTH: usize = 8;
+static MAX_PASSWORD_LENGTH: usize = 128;
+/// Longest message in characters, so a single message can not flood the screen
+static MAX_MESSAGE_LENGTH: usize = 4000;
+
+/// Usernames that could be mistaken for messages from the server
+static RESERVED_NAMES: &[&str] = &["server", "system", "admin"];
+
+pub fn validate_username(username: &str) -> Result<(), RejectReason> {
+    check_name(username).map_err(|problem| RejectReason::InvalidUsername { problem })?;
+
+    if RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(username))
+    {
+        return Err(RejectReason::ReservedName {
+            name: username.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Passwords need to be long enough to not be guessed, the upper limit keeps
+/// hashing cheap
+pub fn validate_password(password: &str) -> Result<(), RejectReason> {
+    let length = password.chars().count();
+    if length < MIN_PASSWORD_LENGTH {
+        return Err(RejectReason::InvalidPassword {
+            problem: format!("must be at least {} characters", MIN_PASSWORD_LENGTH),
+        });
+    }
+    if length > MAX_PASSWORD_LENGTH {
+        return Err(RejectReason::InvalidPassword {
+            problem: format!("must be at most {} characters", MAX_PASSWORD_LENGTH),
+        });
+    }
+    Ok(())
+}
+
+pub fn validate_group(group: &str) -> Result<(), RejectReason> {
+    check_name(group).map_err(|problem| RejectReason::InvalidGroup { problem })
+}
+
+pub fn validate_message(message: &str) -> Result<(), RejectReason> {
+    if message.trim().is_empty() {
+        return Err(RejectReason::EmptyMessage);
+    }
+    if message.chars().count() > MAX_MESSAGE_LENGTH {
+        return Err(RejectReason::MessageTooLong {
+            max: MAX_MESSAGE_LENGTH,
+        });
+    }
+    Ok(())
+}
+
+/// Names are between 1 and 32 characters long and only contain letters,
+/// digits, spaces, '-', '_' and '.'
+fn check_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("must not be empty".to_string());
+    }
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(format!("must be at most {} characters", MAX_NAME_LENGTH));
+    }
+    if name.trim() != name {
+        return Err("must not start or end with whitespace".to_string());
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !(c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.')))
+    {
+        return Err(format!("must not contain '{}'", c));
+    }
+    Ok(())
+}
@@ -1,20 +1,40 @@
+use std::time::Duration;
+
 use log::*;
+use sqlx::SqlitePool;
 use tokio::net::TcpListener;
+use tokio_tungstenite::MaybeTlsStream;
+
+use tokio_native_tls::TlsAcceptor;
 
-use crate::connection::Connection;
+use crate::{
+    connection::{Connection, Heartbeat},
+    nicks::Nicks,
+};
 
+/// How long to wait before accepting again after it failed
+static ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Accept connections on a bound listener, with TLS if an acceptor is given
 pub async fn accept_connections(
-    adress: String,
+    listener: TcpListener,
+    tls: Option<TlsAcceptor>,
     connection_stream: tokio::sync::mpsc::UnboundedSender<Connection>,
+    nicks: Nicks,
+    db: SqlitePool,
+    heartbeat: Heartbeat,
 ) {
-    let listener = TcpListener::bind(&adress)
-        .await
-        .unwrap_or_else(|_| panic!("Failed to bind to adress: {}", &adress));
-
-    info!("Listening on: {}", adress);
-
     debug!("TCP listener started");
-    while let Ok((stream, _)) = listener.accept().await {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            // Mostly out of file descriptors, which passes once connections close
+            Err(e) => {
+                error!("Failed to accept a connection: {}", e);
+                tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                continue;
+            }
+        };
         info!(
             "New TCP connection: {:?}",
             stream
@@ -23,14 +43,38 @@ pub async fn accept_connections(
         );
 
         let c_clone = connection_stream.clone();
+        let nicks = nicks.clone();
+        let db = db.clone();
+        let tls = tls.clone();
         tokio::spawn(async move {
+            let connected_to = stream
+                .peer_addr()
+                .map_or_else(|_| "Unknown".to_owned(), |a| a.to_string());
+            let stream = match tls {
+                Some(acceptor) => match acceptor.accept(stream).await {
+                    Ok(stream) => MaybeTlsStream::NativeTls(stream),
+                    Err(e) => {
+                        error!("<{}> TLS handshake failed: {}", connected_to, e);
+                        return;
+                    }
+                },
+                None => MaybeTlsStream::Plain(stream),
+            };
+
             match tokio_tungstenite::accept_async(stream).await {
                 Ok(ws_stream) => {
-                    info!(
-                        "New WebSocket connection: {:?}",
-                        ws_stream.get_ref().peer_addr()
-                    );
-                    c_clone.send(Connection::new(ws_stream)).unwrap();
+                    info!("New WebSocket connection: {}", connected_to);
+                    match Connection::accept(ws_stream, connected_to, &nicks, &db, heartbeat).await
+                    {
+                        Ok(connection) => {
+                            if c_clone.send(connection).is_err() {
+                                error!(
+                                    "The server stopped taking connections, closing the new one"
+                                );
+                            }
+                        }
+                        Err(e) => info!("Handshake failed: {}", e),
+                    }
                 }
                 Err(e) => {
                     error!("Error during the websocket handshake occurred: {}", e);
@@ -38,5 +82,4 @@ pub async fn accept_connections(
             }
         });
     }
-    debug!("TCP listener ended");
 }